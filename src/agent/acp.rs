@@ -12,12 +12,12 @@ pub struct JsonRpcRequest {
 }
 
 impl JsonRpcRequest {
-    pub fn new(method: &str, params: Option<Value>, id: Option<u64>) -> Self {
+    pub fn new(method: &str, params: Option<Value>, id: Option<Value>) -> Self {
         Self {
             jsonrpc: "2.0".to_string(),
             method: method.to_string(),
             params,
-            id: id.map(|i| i.into()),
+            id,
         }
     }
 
@@ -55,6 +55,34 @@ pub struct InitializeParams {
     pub clientInfo: ClientInfo,
 }
 
+/// Result of `initialize`, as advertised by the agent.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeResult {
+    #[serde(default)]
+    pub agent_capabilities: AgentCapabilities,
+}
+
+/// Capabilities the agent advertised in its `initialize` response.
+/// Anything missing is treated as unsupported.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentCapabilities {
+    #[serde(default)]
+    pub load_session: bool,
+    #[serde(default)]
+    pub prompt_capabilities: PromptCapabilities,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptCapabilities {
+    #[serde(default)]
+    pub image: bool,
+    #[serde(default)]
+    pub embedded_context: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ClientCapabilities {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -129,10 +157,54 @@ pub struct SessionPromptParams {
     pub prompt: Vec<ContentBlock>,
 }
 
+/// Why the agent ended a turn: `stopReason` in the `session/prompt` result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    EndTurn,
+    MaxTokens,
+    MaxTurnRequests,
+    Refusal,
+    Cancelled,
+    /// Reasons newer than this client
+    #[serde(other)]
+    Other,
+}
+
+impl StopReason {
+    /// The stop reason in a `session/prompt` result, if it gives one.
+    pub fn from_result(result: Option<&Value>) -> Option<Self> {
+        serde_json::from_value(result?.get("stopReason")?.clone()).ok()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ContentBlock {
     #[serde(rename = "text")]
     Text { text: String },
-    // Add image/resource types later
+    /// Reference to a resource the agent can access, e.g. a file it wrote
+    #[serde(rename = "resource_link")]
+    ResourceLink {
+        uri: String,
+        name: String,
+        #[serde(rename = "mimeType", default, skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+    },
+    #[serde(rename = "resource")]
+    Resource { resource: EmbeddedResource },
+    /// Block types we don't handle yet (image, audio, ...)
+    #[serde(other)]
+    Unsupported,
+}
+
+/// Contents of a `resource` block: the reference and, for text resources, the
+/// text itself. Blob data is not kept.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddedResource {
+    pub uri: String,
+    #[serde(rename = "mimeType", default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
 }
@@ -0,0 +1,197 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Stops sending prompts to an agent that keeps failing them. After
+/// `threshold` failures in a row, none more than `window` apart from the
+/// first, the circuit opens and prompts are turned away for `cooldown`. Then
+/// one prompt is let through as a probe: its success closes the circuit, its
+/// failure opens it again.
+pub struct CircuitBreaker {
+    threshold: u32,
+    window: Duration,
+    cooldown: Duration,
+    state: Mutex<Circuit>,
+}
+
+#[derive(Default)]
+struct Circuit {
+    // Failures in a row, and when the first of them happened
+    failures: u32,
+    first_failure: Option<Instant>,
+    // Set while open; cleared once a probe succeeds
+    opened_at: Option<Instant>,
+    probing: bool,
+}
+
+/// Whether a prompt may go to the agent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Allowed,
+    /// Allowed as the one prompt testing whether the agent has recovered
+    Probe,
+    /// Turned away; the next probe is possible after `retry_in`
+    Rejected {
+        retry_in: Duration,
+    },
+}
+
+/// A change of the circuit worth telling users about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Opened,
+    Closed,
+}
+
+impl CircuitBreaker {
+    /// A `threshold` of 0 never opens the circuit.
+    pub fn new(threshold: u32, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            window,
+            cooldown,
+            state: Mutex::new(Circuit::default()),
+        }
+    }
+
+    pub fn admit(&self) -> Admission {
+        let mut circuit = self.state.lock().unwrap();
+        let Some(opened_at) = circuit.opened_at else {
+            return Admission::Allowed;
+        };
+
+        let open_for = opened_at.elapsed();
+        if open_for < self.cooldown {
+            return Admission::Rejected {
+                retry_in: self.cooldown - open_for,
+            };
+        }
+        // Half-open: one probe at a time
+        if circuit.probing {
+            return Admission::Rejected {
+                retry_in: Duration::ZERO,
+            };
+        }
+        circuit.probing = true;
+        Admission::Probe
+    }
+
+    /// Record that the agent answered a prompt.
+    pub fn record_success(&self) -> Option<Transition> {
+        let mut circuit = self.state.lock().unwrap();
+        let was_open = circuit.opened_at.is_some();
+        *circuit = Circuit::default();
+        was_open.then_some(Transition::Closed)
+    }
+
+    /// Record that a prompt failed.
+    pub fn record_failure(&self) -> Option<Transition> {
+        if self.threshold == 0 {
+            return None;
+        }
+        let mut circuit = self.state.lock().unwrap();
+
+        if circuit.probing {
+            // The probe failed: stay open for another cooldown
+            circuit.probing = false;
+            circuit.opened_at = Some(Instant::now());
+            return None;
+        }
+        if circuit.opened_at.is_some() {
+            return None;
+        }
+
+        let stale = circuit
+            .first_failure
+            .is_some_and(|first| first.elapsed() > self.window);
+        if stale || circuit.failures == 0 {
+            circuit.failures = 0;
+            circuit.first_failure = Some(Instant::now());
+        }
+        circuit.failures += 1;
+
+        if circuit.failures < self.threshold {
+            return None;
+        }
+        circuit.opened_at = Some(Instant::now());
+        Some(Transition::Opened)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_millis(50);
+
+    fn breaker(threshold: u32) -> CircuitBreaker {
+        CircuitBreaker::new(threshold, Duration::from_secs(60), COOLDOWN)
+    }
+
+    fn is_rejected(admission: Admission) -> bool {
+        matches!(admission, Admission::Rejected { .. })
+    }
+
+    #[test]
+    fn consecutive_failures_open_the_circuit_until_a_probe_succeeds() {
+        let circuit = breaker(3);
+        assert_eq!(circuit.record_failure(), None);
+        assert_eq!(circuit.record_failure(), None);
+        assert_eq!(circuit.admit(), Admission::Allowed);
+        assert_eq!(circuit.record_failure(), Some(Transition::Opened));
+
+        let Admission::Rejected { retry_in } = circuit.admit() else {
+            panic!("an open circuit turns prompts away");
+        };
+        assert!(retry_in > Duration::ZERO && retry_in <= COOLDOWN);
+
+        std::thread::sleep(COOLDOWN);
+        assert_eq!(circuit.admit(), Admission::Probe);
+        // Only one probe at a time
+        assert_eq!(
+            circuit.admit(),
+            Admission::Rejected {
+                retry_in: Duration::ZERO
+            }
+        );
+        assert_eq!(circuit.record_success(), Some(Transition::Closed));
+        assert_eq!(circuit.admit(), Admission::Allowed);
+    }
+
+    #[test]
+    fn a_failed_probe_starts_another_cooldown() {
+        let circuit = breaker(1);
+        assert_eq!(circuit.record_failure(), Some(Transition::Opened));
+        std::thread::sleep(COOLDOWN);
+
+        assert_eq!(circuit.admit(), Admission::Probe);
+        assert_eq!(circuit.record_failure(), None);
+        assert!(is_rejected(circuit.admit()));
+
+        std::thread::sleep(COOLDOWN);
+        assert_eq!(circuit.admit(), Admission::Probe);
+        assert_eq!(circuit.record_success(), Some(Transition::Closed));
+    }
+
+    #[test]
+    fn a_success_or_a_quiet_window_resets_the_count() {
+        let circuit = breaker(2);
+        circuit.record_failure();
+        assert_eq!(circuit.record_success(), None);
+        assert_eq!(circuit.record_failure(), None);
+
+        let circuit = CircuitBreaker::new(2, Duration::from_millis(20), COOLDOWN);
+        circuit.record_failure();
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(circuit.record_failure(), None);
+        assert_eq!(circuit.record_failure(), Some(Transition::Opened));
+    }
+
+    #[test]
+    fn a_zero_threshold_never_opens() {
+        let circuit = breaker(0);
+        for _ in 0..10 {
+            assert_eq!(circuit.record_failure(), None);
+        }
+        assert_eq!(circuit.admit(), Admission::Allowed);
+    }
+}
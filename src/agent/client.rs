@@ -1,41 +1,355 @@
 use crate::agent::acp::{
-    ClientCapabilities, ClientInfo, ContentBlock, FsCapabilities, InitializeParams, JsonRpcRequest,
-    JsonRpcResponse, SessionNewParams, SessionPromptParams,
+    AgentCapabilities, ClientCapabilities, ClientInfo, ContentBlock, FsCapabilities,
+    InitializeParams, InitializeResult, JsonRpcRequest, JsonRpcResponse, SessionNewParams,
+    SessionPromptParams,
 };
 use anyhow::{Context, Result};
+use serde::Deserialize;
 use serde_json::Value;
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Write};
-use std::process::Child;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ExitStatus};
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
-use tokio::sync::{broadcast, mpsc, oneshot};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
 use tokio::task;
 use tracing::{debug, error, info, warn};
 
+/// Default buffer for agent notifications. Agents can emit hundreds of tiny
+/// `session/update` chunks in a burst, so this is sized well above the request queue.
+pub const DEFAULT_NOTIFICATION_CAPACITY: usize = 1024;
+
+/// How many answered request ids to remember for duplicate detection.
+const COMPLETED_ID_HISTORY: usize = 256;
+
+/// Outgoing messages buffered for the agent's stdin writer.
+const WRITE_QUEUE_CAPACITY: usize = 100;
+
+/// Default for how long a message may wait for room in a full write queue
+/// before we give up.
+pub const DEFAULT_WRITE_QUEUE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Returned when the agent stops reading its stdin and the write queue stays
+/// full for longer than the client's write queue timeout.
+#[derive(Debug)]
+pub struct AgentInputBackpressure {
+    pub method: String,
+    pub after: Duration,
+}
+
+impl fmt::Display for AgentInputBackpressure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Agent input backpressure: '{}' not queued within {:?}, the agent is not reading its input",
+            self.method, self.after
+        )
+    }
+}
+
+impl std::error::Error for AgentInputBackpressure {}
+
+/// Default for how long a request waits while the agent sends nothing at all.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Returned by [`AcpClient::send_request`] when the agent neither answered
+/// nor sent anything else for the client's request timeout.
+#[derive(Debug)]
+pub struct AcpRequestTimeout {
+    pub method: String,
+    pub after: Duration,
+}
+
+impl fmt::Display for AcpRequestTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ACP request '{}' timed out: no word from the agent for {:?}",
+            self.method, self.after
+        )
+    }
+}
+
+impl std::error::Error for AcpRequestTimeout {}
+
+/// How JSON-RPC request ids are generated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestIdStrategy {
+    /// 1, 2, 3, ... per client
+    #[default]
+    Sequential,
+    /// Random UUID strings, unique across client instances
+    Uuid,
+}
+
+impl FromStr for RequestIdStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "sequential" => Ok(RequestIdStrategy::Sequential),
+            "uuid" => Ok(RequestIdStrategy::Uuid),
+            other => anyhow::bail!("Unknown request id strategy: {}", other),
+        }
+    }
+}
+
+/// Recent agent stderr lines kept for diagnostics.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// Read buffer for agent stdout; large enough that big messages need few reads.
+const READ_BUFFER_BYTES: usize = 64 * 1024;
+
+/// Longest single message accepted from an agent. Longer lines are skipped
+/// rather than buffered without bound.
+const MAX_LINE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Outcome of reading one newline-delimited message from the agent.
+enum ReadLine {
+    /// `line` holds a whole message (a final one may lack its newline)
+    Complete,
+    /// The message exceeded `MAX_LINE_BYTES` and was discarded; carries its length
+    TooLong(usize),
+    Eof,
+}
+
+/// Accumulate bytes into `line` until a newline, however many reads that takes.
+fn read_line_bounded(reader: &mut impl BufRead, line: &mut Vec<u8>) -> std::io::Result<ReadLine> {
+    let read = reader
+        .by_ref()
+        .take(MAX_LINE_BYTES as u64 + 1)
+        .read_until(b'\n', line)?;
+    if read == 0 {
+        return Ok(ReadLine::Eof);
+    }
+    if line.ends_with(b"\n") || line.len() <= MAX_LINE_BYTES {
+        return Ok(ReadLine::Complete);
+    }
+
+    // Over the limit: drop the rest of this line without keeping it
+    let mut skipped = line.len();
+    line.clear();
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            break;
+        }
+        match available.iter().position(|&b| b == b'\n') {
+            Some(end) => {
+                skipped += end + 1;
+                reader.consume(end + 1);
+                break;
+            }
+            None => {
+                let len = available.len();
+                skipped += len;
+                reader.consume(len);
+            }
+        }
+    }
+    Ok(ReadLine::TooLong(skipped))
+}
+
+/// Pull the session id out of a `session/new` result. Agents disagree on where
+/// it goes, so several common shapes are accepted; numeric ids are stringified.
+fn extract_session_id(result: &Value) -> Option<String> {
+    let as_id = |v: &Value| match v {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    };
+
+    // Spec shape first, then variants seen in the wild, then a bare id
+    [
+        "/sessionId",
+        "/session_id",
+        "/session/id",
+        "/session/sessionId",
+        "/id",
+    ]
+    .iter()
+    .find_map(|path| result.pointer(path).and_then(as_id))
+    .or_else(|| as_id(result))
+}
+
+/// Requests awaiting a response, plus a bounded memory of recently answered or
+/// abandoned ids so a late response is recognized instead of reported as unknown.
+#[derive(Default)]
+struct PendingRequests {
+    waiting: HashMap<String, oneshot::Sender<JsonRpcResponse>>,
+    completed: VecDeque<String>,
+    // Set once the agent's stdout is gone; nothing can be answered after that
+    closed: bool,
+}
+
+impl PendingRequests {
+    fn insert(&mut self, id: String, tx: oneshot::Sender<JsonRpcResponse>) {
+        // Dropping `tx` fails the request straight away instead of leaving it hanging
+        if !self.closed {
+            self.waiting.insert(id, tx);
+        }
+    }
+
+    /// Fail every waiting request, and any made later, because the agent
+    /// stopped answering (e.g. its process exited).
+    fn close(&mut self) {
+        self.closed = true;
+        self.waiting.clear();
+    }
+
+    /// Take the waiter for `id`, remembering it as answered.
+    fn complete(&mut self, id: &str) -> Option<oneshot::Sender<JsonRpcResponse>> {
+        let tx = self.waiting.remove(id)?;
+        self.remember(id);
+        Some(tx)
+    }
+
+    /// Drop the waiter of a request whose caller stopped waiting. Returns
+    /// whether it was still waiting.
+    fn abandon(&mut self, id: &str) -> bool {
+        if self.waiting.remove(id).is_none() {
+            return false;
+        }
+        self.remember(id);
+        true
+    }
+
+    fn remember(&mut self, id: &str) {
+        if self.completed.len() == COMPLETED_ID_HISTORY {
+            self.completed.pop_front();
+        }
+        self.completed.push_back(id.to_string());
+    }
+
+    /// Forget a request that never reached the agent.
+    fn cancel(&mut self, id: &str) {
+        self.waiting.remove(id);
+    }
+
+    fn was_completed(&self, id: &str) -> bool {
+        self.completed.iter().any(|done| done == id)
+    }
+}
+
+/// Removes a request from [`PendingRequests`] if its `send_request` future is
+/// dropped before the response arrives, e.g. when the caller times out.
+struct PendingGuard<'a> {
+    pending: &'a Mutex<PendingRequests>,
+    id: String,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        // Already gone if the response arrived or the request was never sent
+        if self.pending.lock().unwrap().abandon(&self.id) {
+            debug!(
+                "Request {} was abandoned before its response arrived",
+                self.id
+            );
+        }
+    }
+}
+
 pub struct AcpClient {
     tx_request: mpsc::Sender<JsonRpcRequest>,
-    pending_requests: Arc<Mutex<HashMap<String, oneshot::Sender<JsonRpcResponse>>>>,
+    pending_requests: Arc<Mutex<PendingRequests>>,
     pub notification_tx: broadcast::Sender<JsonRpcRequest>,
     request_id_counter: Arc<Mutex<u64>>,
+    id_strategy: RequestIdStrategy,
+    // Filled in by `initialize`; empty (all unsupported) until then
+    agent_capabilities: Mutex<AgentCapabilities>,
+    // Model the agent picked for the last `session/new`, if it reports one
+    default_model: Mutex<Option<String>>,
+    // The agent process, until it exits or is killed; None for `from_io` clients
+    child: Arc<Mutex<Option<Child>>>,
+    // Last lines the agent wrote to stderr, if its stderr is piped to us
+    stderr_tail: Arc<Mutex<VecDeque<String>>>,
+    // When the agent last wrote a message to stdout
+    last_heard: Arc<Mutex<Instant>>,
+    // Set when the agent process exits on its own (not through `kill`)
+    exit_rx: watch::Receiver<Option<ExitStatus>>,
+    request_timeout: Duration,
+    write_queue_timeout: Duration,
 }
 
 impl AcpClient {
-    pub fn new(mut child: Child) -> Result<Self> {
+    pub fn new(child: Child) -> Result<Self> {
+        Self::with_notification_capacity(child, DEFAULT_NOTIFICATION_CAPACITY)
+    }
+
+    pub fn with_notification_capacity(
+        mut child: Child,
+        notification_capacity: usize,
+    ) -> Result<Self> {
         let stdin = child.stdin.take().context("Failed to take stdin")?;
         let stdout = child.stdout.take().context("Failed to take stdout")?;
+        let stderr = child.stderr.take();
 
-        let (tx_request, mut rx_request) = mpsc::channel::<JsonRpcRequest>(100);
-        let (notification_tx, _) = broadcast::channel(100);
+        let mut client = Self::from_io(stdin, stdout, notification_capacity);
+        client.child = Arc::new(Mutex::new(Some(child)));
+        let (exit_tx, exit_rx) = watch::channel(None);
+        client.exit_rx = exit_rx;
 
-        let pending_requests: Arc<Mutex<HashMap<String, oneshot::Sender<JsonRpcResponse>>>> =
-            Arc::new(Mutex::new(HashMap::new()));
+        if let Some(stderr) = stderr {
+            let tail = client.stderr_tail.clone();
+            task::spawn_blocking(move || {
+                for line in BufReader::new(stderr).lines() {
+                    let Ok(line) = line else { break };
+                    debug!("agent stderr: {}", line);
+                    let mut tail = tail.lock().unwrap();
+                    if tail.len() == STDERR_TAIL_LINES {
+                        tail.pop_front();
+                    }
+                    tail.push_back(line);
+                }
+            });
+        }
+
+        // Reap the agent process once it exits, unless `kill` takes it first
+        let child = client.child.clone();
+        task::spawn_blocking(move || loop {
+            std::thread::sleep(std::time::Duration::from_secs(1));
+            let mut guard = child.lock().unwrap();
+            match guard.as_mut().map(|c| c.try_wait()) {
+                Some(Ok(None)) => continue,
+                Some(Ok(Some(status))) => {
+                    guard.take();
+                    let _ = exit_tx.send(Some(status));
+                    break;
+                }
+                _ => {
+                    guard.take();
+                    break;
+                }
+            }
+        });
+
+        Ok(client)
+    }
+
+    /// Speak line-delimited JSON-RPC over any byte streams, e.g. a child's stdio
+    /// or an in-memory pipe.
+    pub fn from_io<W, R>(writer: W, reader: R, notification_capacity: usize) -> Self
+    where
+        W: Write + Send + 'static,
+        R: Read + Send + 'static,
+    {
+        let (tx_request, mut rx_request) = mpsc::channel::<JsonRpcRequest>(WRITE_QUEUE_CAPACITY);
+        let (notification_tx, _) = broadcast::channel(notification_capacity);
+
+        let pending_requests = Arc::new(Mutex::new(PendingRequests::default()));
 
         let pending_requests_clone = pending_requests.clone();
         let notification_tx_clone = notification_tx.clone();
+        let last_heard = Arc::new(Mutex::new(Instant::now()));
+        let last_heard_clone = last_heard.clone();
 
         // Stdin Writer Task (Blocking)
         task::spawn_blocking(move || {
-            let mut stdin = stdin;
+            let mut writer = writer;
             while let Some(req) = rx_request.blocking_recv() {
                 let json_str = match serde_json::to_string(&req) {
                     Ok(s) => s,
@@ -48,7 +362,7 @@ impl AcpClient {
                 debug!("-> Sending to Agent: {}", json_str);
 
                 // Using Line-Delimited JSON
-                if let Err(e) = writeln!(stdin, "{}", json_str) {
+                if let Err(e) = writeln!(writer, "{}", json_str) {
                     error!("Failed to write to agent stdin: {}", e);
                     break;
                 }
@@ -58,96 +372,209 @@ impl AcpClient {
 
         // Stdout Reader Task (Blocking)
         task::spawn_blocking(move || {
-            let reader = BufReader::new(stdout);
-            for line in reader.lines() {
-                match line {
-                    Ok(line) => {
-                        if line.trim().is_empty() {
-                            continue;
-                        }
-                        debug!("<- Received from Agent: {}", line);
-
-                        // Try parsing as Response first
-                        if let Ok(response) = serde_json::from_str::<JsonRpcResponse>(&line) {
-                            // It's a response to one of our requests
-                            let id_str = response.id.to_string(); // Simple normalization
-                                                                  // Remove quotes if string id
-                            let id_clean = id_str.trim_matches('"').to_string();
-
-                            let sender = {
-                                let mut pending = pending_requests_clone.lock().unwrap();
-                                pending.remove(&id_clean)
-                            };
-
-                            if let Some(tx) = sender {
-                                let _ = tx.send(response);
-                            } else {
-                                warn!("Received response for unknown ID: {}", id_clean);
-                            }
-                        } else if let Ok(request) = serde_json::from_str::<JsonRpcRequest>(&line) {
-                            // It's a notification or method call from the agent
-                            let _ = notification_tx_clone.send(request);
-                        } else {
-                            error!("Failed to parse agent message: {}", line);
-                        }
+            let mut reader = BufReader::with_capacity(READ_BUFFER_BYTES, reader);
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match read_line_bounded(&mut reader, &mut line) {
+                    Ok(ReadLine::Complete) => {
+                        *last_heard_clone.lock().unwrap() = Instant::now();
+                        let text = String::from_utf8_lossy(&line);
+                        dispatch_line(
+                            text.trim_end_matches(['\n', '\r']),
+                            &pending_requests_clone,
+                            &notification_tx_clone,
+                        )
                     }
+                    Ok(ReadLine::TooLong(len)) => {
+                        error!(
+                            "Skipped a {} byte message from the agent (limit {} bytes)",
+                            len, MAX_LINE_BYTES
+                        );
+                    }
+                    Ok(ReadLine::Eof) => break,
                     Err(e) => {
                         error!("Error reading from agent stdout: {}", e);
                         break;
                     }
                 }
             }
+            pending_requests_clone.lock().unwrap().close();
             debug!("Stdout reader task finished");
-            // Optionally wait for child
-            let _ = child.wait();
         });
 
-        Ok(Self {
+        Self {
             tx_request,
             pending_requests,
             notification_tx,
             request_id_counter: Arc::new(Mutex::new(1)),
-        })
+            id_strategy: RequestIdStrategy::default(),
+            agent_capabilities: Mutex::new(AgentCapabilities::default()),
+            default_model: Mutex::new(None),
+            child: Arc::new(Mutex::new(None)),
+            stderr_tail: Arc::new(Mutex::new(VecDeque::new())),
+            last_heard,
+            // No process to watch; the sender is gone, so this never changes
+            exit_rx: watch::channel(None).1,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            write_queue_timeout: DEFAULT_WRITE_QUEUE_TIMEOUT,
+        }
     }
 
+    /// Whether the agent process is still running. Always false for `from_io` clients.
+    pub fn is_alive(&self) -> bool {
+        self.child.lock().unwrap().is_some()
+    }
+
+    /// Becomes `Some` with the exit status once the agent process exits by
+    /// itself. Killing it through [`AcpClient::kill`] doesn't count.
+    pub fn exit_watch(&self) -> watch::Receiver<Option<ExitStatus>> {
+        self.exit_rx.clone()
+    }
+
+    /// The agent's most recent stderr lines, oldest first.
+    pub fn stderr_tail(&self) -> Vec<String> {
+        self.stderr_tail.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn with_id_strategy(mut self, id_strategy: RequestIdStrategy) -> Self {
+        self.id_strategy = id_strategy;
+        self
+    }
+
+    /// Fail requests once the agent has sent nothing for `timeout`, response
+    /// or otherwise, so a busy agent streaming updates isn't cut off. Zero
+    /// waits forever.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Fail a request or notification that finds the write queue full for
+    /// `timeout`, because the agent stopped reading its input.
+    pub fn with_write_queue_timeout(mut self, timeout: Duration) -> Self {
+        self.write_queue_timeout = timeout;
+        self
+    }
+
+    fn next_request_id(&self) -> Value {
+        match self.id_strategy {
+            RequestIdStrategy::Sequential => {
+                let mut counter = self.request_id_counter.lock().unwrap();
+                let id = *counter;
+                *counter += 1;
+                Value::from(id)
+            }
+            RequestIdStrategy::Uuid => Value::from(uuid::Uuid::new_v4().to_string()),
+        }
+    }
+
+    /// Kill the agent process. A no-op if it already exited.
+    pub fn kill(&self) -> Result<()> {
+        let Some(mut child) = self.child.lock().unwrap().take() else {
+            return Ok(());
+        };
+        if child.try_wait()?.is_none() {
+            child.kill().context("Failed to kill agent process")?;
+        }
+        child.wait()?;
+        Ok(())
+    }
+
+    /// Send a request and wait for its response. The request timeout is not a
+    /// deadline for this request: it fails only once the agent has been silent
+    /// that long. Anything the agent sends, including updates or responses for
+    /// other requests, keeps it waiting, so a request may run far longer than
+    /// the timeout while the agent is still talking.
     pub async fn send_request(
         &self,
         method: &str,
         params: Option<Value>,
     ) -> Result<JsonRpcResponse> {
-        let id = {
-            let mut counter = self.request_id_counter.lock().unwrap();
-            let id = *counter;
-            *counter += 1;
-            id
-        };
-
-        let req = JsonRpcRequest::new(method, params, Some(id));
-        let (tx, rx) = oneshot::channel();
+        let id = self.next_request_id();
+        let key = request_id_key(&id);
+        let (tx, mut rx) = oneshot::channel();
 
         {
             let mut pending = self.pending_requests.lock().unwrap();
-            pending.insert(id.to_string(), tx);
+            pending.insert(key.clone(), tx);
         }
+        let _guard = PendingGuard {
+            pending: &self.pending_requests,
+            id: key.clone(),
+        };
 
-        self.tx_request
-            .send(req)
-            .await
-            .context("Failed to send request to writer loop")?;
+        let req = JsonRpcRequest::new(method, params, Some(id));
 
-        let response = rx.await.context("Response channel closed")?;
-        Ok(response)
+        if let Err(e) = self.enqueue(req).await {
+            self.pending_requests.lock().unwrap().cancel(&key);
+            return Err(e.context("Failed to send request to writer loop"));
+        }
+
+        // Dropping `_guard` on timeout takes the request out of `pending_requests`
+        let sent = Instant::now();
+        let response = loop {
+            if self.request_timeout.is_zero() {
+                break (&mut rx).await;
+            }
+            let idle = self
+                .last_heard
+                .lock()
+                .unwrap()
+                .elapsed()
+                .min(sent.elapsed());
+            if idle >= self.request_timeout {
+                warn!(
+                    "Request {} ('{}') timed out after {:?} of agent silence",
+                    key, method, idle
+                );
+                return Err(AcpRequestTimeout {
+                    method: method.to_string(),
+                    after: self.request_timeout,
+                }
+                .into());
+            }
+            // On expiry, check again: the agent may have sent something meanwhile
+            if let Ok(response) = tokio::time::timeout(self.request_timeout - idle, &mut rx).await {
+                break response;
+            }
+        };
+        response.context("Agent closed its output before responding")
     }
 
     pub async fn send_notification(&self, method: &str, params: Option<Value>) -> Result<()> {
         let req = JsonRpcRequest::notification(method, params);
-        self.tx_request
-            .send(req)
+        self.enqueue(req)
             .await
             .context("Failed to send notification")?;
         Ok(())
     }
 
+    /// Hand a message to the stdin writer, waiting at most the write queue
+    /// timeout for room so a stalled agent surfaces as an error instead of a hang.
+    async fn enqueue(&self, req: JsonRpcRequest) -> Result<()> {
+        let queued = self.tx_request.max_capacity() - self.tx_request.capacity();
+        if queued >= WRITE_QUEUE_CAPACITY / 2 {
+            warn!(
+                "Agent write queue is filling up: {}/{} messages waiting",
+                queued, WRITE_QUEUE_CAPACITY
+            );
+        }
+
+        let method = req.method.clone();
+        let after = self.write_queue_timeout;
+        match tokio::time::timeout(after, self.tx_request.send(req)).await {
+            Ok(sent) => sent.map_err(|_| anyhow::anyhow!("Agent writer loop has stopped")),
+            Err(_) => {
+                error!(
+                    "Agent write queue full ({} messages) for {:?}, dropping '{}'",
+                    WRITE_QUEUE_CAPACITY, after, method
+                );
+                Err(AgentInputBackpressure { method, after }.into())
+            }
+        }
+    }
+
     // --- High Level Methods ---
 
     pub async fn initialize(&self) -> Result<()> {
@@ -176,9 +603,46 @@ impl AcpClient {
         }
 
         info!("ACP Initialized: {:?}", response.result);
+
+        match response
+            .result
+            .map(serde_json::from_value::<InitializeResult>)
+            .transpose()
+        {
+            Ok(Some(result)) => {
+                info!("Agent capabilities: {:?}", result.agent_capabilities);
+                *self.agent_capabilities.lock().unwrap() = result.agent_capabilities;
+            }
+            Ok(None) => warn!("Initialize returned no result, assuming no agent capabilities"),
+            Err(e) => warn!("Could not parse agent capabilities: {}", e),
+        }
+
         Ok(())
     }
 
+    /// Whether the agent can resume an existing session via `session/load`.
+    pub fn supports_load_session(&self) -> bool {
+        self.agent_capabilities.lock().unwrap().load_session
+    }
+
+    /// Whether prompts may carry `image` content blocks.
+    pub fn supports_image_content(&self) -> bool {
+        self.agent_capabilities
+            .lock()
+            .unwrap()
+            .prompt_capabilities
+            .image
+    }
+
+    /// Whether prompts may carry embedded `resource` content blocks.
+    pub fn supports_embedded_context(&self) -> bool {
+        self.agent_capabilities
+            .lock()
+            .unwrap()
+            .prompt_capabilities
+            .embedded_context
+    }
+
     pub async fn new_session(&self, cwd: &str) -> Result<String> {
         let params = SessionNewParams {
             cwd: cwd.to_string(),
@@ -193,27 +657,40 @@ impl AcpClient {
             anyhow::bail!("session/new failed: {}", err.message);
         }
 
-        // Parse result. Assuming result is { "sessionId": "..." } or just the ID string?
-        // Let's assume the result object contains the ID.
         let result = response.result.context("No result in session/new")?;
 
-        // Try to extract sessionId
-        let session_id = result
-            .get("sessionId")
+        let Some(session_id) = extract_session_id(&result) else {
+            debug!("Unrecognized session/new result: {}", result);
+            anyhow::bail!("Could not parse sessionId from result");
+        };
+
+        *self.default_model.lock().unwrap() = result
+            .pointer("/models/currentModelId")
             .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
-            .or_else(|| result.as_str().map(|s| s.to_string())) // Maybe it returns just the string
-            .context("Could not parse sessionId from result")?;
+            .map(|s| s.to_string());
 
         Ok(session_id)
     }
 
     pub async fn prompt(&self, session_id: &str, content: &str) -> Result<JsonRpcResponse> {
-        let params = SessionPromptParams {
-            sessionId: session_id.to_string(),
-            prompt: vec![ContentBlock::Text {
+        self.prompt_blocks(
+            session_id,
+            vec![ContentBlock::Text {
                 text: content.to_string(),
             }],
+        )
+        .await
+    }
+
+    /// Like [`AcpClient::prompt`], with the prompt made of several content blocks.
+    pub async fn prompt_blocks(
+        &self,
+        session_id: &str,
+        prompt: Vec<ContentBlock>,
+    ) -> Result<JsonRpcResponse> {
+        let params = SessionPromptParams {
+            sessionId: session_id.to_string(),
+            prompt,
         };
 
         // session/prompt returns when the turn is complete.
@@ -230,4 +707,485 @@ impl AcpClient {
 
         Ok(response)
     }
+
+    /// Ask the agent to stop the in-flight turn; the pending `session/prompt` then
+    /// completes with a `cancelled` stop reason.
+    pub async fn cancel(&self, session_id: &str) -> Result<()> {
+        self.send_notification(
+            "session/cancel",
+            Some(serde_json::json!({ "sessionId": session_id })),
+        )
+        .await
+    }
+
+    /// Model the agent started the session with, used to undo `/model` switches.
+    pub fn default_model(&self) -> Option<String> {
+        self.default_model.lock().unwrap().clone()
+    }
+
+    /// Switch the model used for subsequent turns of `session_id`.
+    pub async fn set_model(&self, session_id: &str, model_id: &str) -> Result<()> {
+        let response = self
+            .send_request(
+                "session/set_model",
+                Some(serde_json::json!({ "sessionId": session_id, "modelId": model_id })),
+            )
+            .await?;
+
+        if let Some(err) = response.error {
+            anyhow::bail!("session/set_model failed: {}", err.message);
+        }
+        Ok(())
+    }
+}
+
+/// Map key for a request id; string and numeric ids share one namespace.
+fn request_id_key(id: &Value) -> String {
+    match id {
+        Value::String(id) => id.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Route one line of agent output: responses go to their pending waiter,
+/// anything else that parses as a request is broadcast as a notification.
+fn dispatch_line(
+    line: &str,
+    pending_requests: &Mutex<PendingRequests>,
+    notification_tx: &broadcast::Sender<JsonRpcRequest>,
+) {
+    if line.trim().is_empty() {
+        return;
+    }
+    debug!("<- Received from Agent: {}", line);
+
+    // Try parsing as Response first
+    if let Ok(response) = serde_json::from_str::<JsonRpcResponse>(line) {
+        // It's a response to one of our requests
+        let id_clean = request_id_key(&response.id);
+
+        let mut pending = pending_requests.lock().unwrap();
+        if let Some(tx) = pending.complete(&id_clean) {
+            let _ = tx.send(response);
+        } else if pending.was_completed(&id_clean) {
+            warn!(
+                "Dropping late response for already answered or abandoned request {}",
+                id_clean
+            );
+        } else {
+            warn!("Received response for unknown ID: {}", id_clean);
+        }
+    } else if let Ok(request) = serde_json::from_str::<JsonRpcRequest>(line) {
+        // It's a notification or method call from the agent
+        let _ = notification_tx.send(request);
+    } else {
+        error!("Failed to parse agent message: {}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// One line of agent output.
+    #[derive(Debug, Clone)]
+    enum Line {
+        /// Answer to our request with this id
+        Response(u64, String),
+        Notification(String),
+        /// Blank or whitespace-only line
+        Blank(String),
+    }
+
+    impl Line {
+        fn render(&self, padding: &str, crlf: bool) -> String {
+            let body = match self {
+                Line::Response(id, text) => serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": { "text": text }
+                })
+                .to_string(),
+                Line::Notification(text) => serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "method": "session/update",
+                    "params": { "text": text }
+                })
+                .to_string(),
+                Line::Blank(blank) => return format!("{}\n", blank),
+            };
+            let end = if crlf { "\r\n" } else { "\n" };
+            format!("{}{}{}{}", padding, body, padding, end)
+        }
+    }
+
+    /// Responses to requests 1..=n, notifications and blank lines, interleaved.
+    fn agent_output() -> impl Strategy<Value = Vec<(Line, String, bool)>> {
+        (0u64..8)
+            .prop_flat_map(|n| {
+                let responses = proptest::collection::vec(any::<String>(), n as usize);
+                let notifications = proptest::collection::vec(any::<String>(), 0..8);
+                let blanks = proptest::collection::vec("[ \t]*", 0..6);
+                (responses, notifications, blanks)
+            })
+            .prop_flat_map(|(responses, notifications, blanks)| {
+                let lines: Vec<Line> = responses
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, text)| Line::Response(i as u64 + 1, text))
+                    .chain(notifications.into_iter().map(Line::Notification))
+                    .chain(blanks.into_iter().map(Line::Blank))
+                    .collect();
+                let len = lines.len();
+                (
+                    Just(lines).prop_shuffle(),
+                    proptest::collection::vec(("[ \t]{0,2}", any::<bool>()), len),
+                )
+            })
+            .prop_map(|(lines, framing)| {
+                lines
+                    .into_iter()
+                    .zip(framing)
+                    .map(|(line, (padding, crlf))| (line, padding, crlf))
+                    .collect()
+            })
+    }
+
+    fn feed(lines: Vec<(Line, String, bool)>) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async move {
+            let (reader, mut writer) = std::io::pipe().unwrap();
+            let client = AcpClient::from_io(std::io::sink(), reader, DEFAULT_NOTIFICATION_CAPACITY);
+            let mut notifications = client.notification_tx.subscribe();
+
+            let expected_responses: HashMap<u64, String> = lines
+                .iter()
+                .filter_map(|(line, _, _)| match line {
+                    Line::Response(id, text) => Some((*id, text.clone())),
+                    _ => None,
+                })
+                .collect();
+            let expected_notifications: Vec<String> = lines
+                .iter()
+                .filter_map(|(line, _, _)| match line {
+                    Line::Notification(text) => Some(text.clone()),
+                    _ => None,
+                })
+                .collect();
+
+            // Register a waiter for every response before the agent says anything
+            let requests = futures::future::join_all(
+                (0..expected_responses.len()).map(|_| client.send_request("test", None)),
+            );
+            tokio::pin!(requests);
+            assert!(futures::poll!(&mut requests).is_pending() || expected_responses.is_empty());
+
+            let output: String = lines
+                .iter()
+                .map(|(line, padding, crlf)| line.render(padding, *crlf))
+                .collect();
+            task::spawn_blocking(move || {
+                writer.write_all(output.as_bytes()).unwrap();
+            });
+
+            for response in requests.await {
+                let response = response.expect("every request gets its response");
+                let id = response.id.as_u64().unwrap();
+                assert_eq!(
+                    response.result.unwrap()["text"].as_str(),
+                    Some(expected_responses[&id].as_str())
+                );
+            }
+            for expected in expected_notifications {
+                let notification =
+                    tokio::time::timeout(Duration::from_secs(5), notifications.recv())
+                        .await
+                        .expect("notification should be broadcast")
+                        .unwrap();
+                assert_eq!(
+                    notification.params.unwrap()["text"].as_str(),
+                    Some(expected.as_str())
+                );
+            }
+        });
+    }
+
+    proptest! {
+        #[test]
+        fn every_message_reaches_its_waiter_or_subscribers(lines in agent_output()) {
+            feed(lines);
+        }
+    }
+
+    #[test]
+    fn an_unterminated_final_line_is_complete() {
+        let mut reader = "{\"a\":1}".as_bytes();
+        let mut line = Vec::new();
+        assert!(matches!(
+            read_line_bounded(&mut reader, &mut line),
+            Ok(ReadLine::Complete)
+        ));
+        assert_eq!(line, b"{\"a\":1}");
+        assert!(matches!(
+            read_line_bounded(&mut reader, &mut line),
+            Ok(ReadLine::Eof)
+        ));
+    }
+
+    #[test]
+    fn session_ids_are_found_in_every_accepted_shape() {
+        use serde_json::json;
+
+        for result in [
+            json!({ "sessionId": "abc" }),
+            json!({ "session_id": "abc" }),
+            json!({ "session": { "id": "abc" } }),
+            json!({ "session": { "sessionId": "abc" } }),
+            json!({ "id": "abc" }),
+            json!("abc"),
+            json!({ "sessionId": "  abc\n" }),
+        ] {
+            assert_eq!(
+                extract_session_id(&result).as_deref(),
+                Some("abc"),
+                "{}",
+                result
+            );
+        }
+        assert_eq!(
+            extract_session_id(&json!({ "sessionId": 42 })).as_deref(),
+            Some("42")
+        );
+        // The spec's field wins over the others
+        assert_eq!(
+            extract_session_id(&json!({ "id": "other", "sessionId": "abc" })).as_deref(),
+            Some("abc")
+        );
+    }
+
+    #[test]
+    fn blank_or_missing_session_ids_are_rejected() {
+        use serde_json::json;
+
+        for result in [
+            json!({}),
+            json!({ "sessionId": "" }),
+            json!({ "sessionId": "   " }),
+            json!({ "sessionId": null }),
+            json!({ "sessionId": { "value": "abc" } }),
+            json!({ "session": "abc" }),
+            json!(["abc"]),
+            json!(null),
+            json!(true),
+        ] {
+            assert_eq!(extract_session_id(&result), None, "{}", result);
+        }
+    }
+
+    #[tokio::test]
+    async fn dropped_requests_leave_the_pending_map() {
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        let client = Arc::new(AcpClient::from_io(
+            std::io::sink(),
+            reader,
+            DEFAULT_NOTIFICATION_CAPACITY,
+        ));
+
+        // A caller that gives up, and a task that is aborted while waiting
+        let timed_out =
+            tokio::time::timeout(Duration::from_millis(50), client.send_request("test", None));
+        assert!(timed_out.await.is_err());
+        let aborted = tokio::spawn({
+            let client = client.clone();
+            async move { client.send_request("test", None).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(client.pending_requests.lock().unwrap().waiting.len(), 1);
+        aborted.abort();
+        let _ = aborted.await;
+
+        {
+            let pending = client.pending_requests.lock().unwrap();
+            assert!(pending.waiting.is_empty());
+            assert!(pending.was_completed("1") && pending.was_completed("2"));
+        }
+
+        // Their late responses are dropped and later requests still get theirs
+        let answered = tokio::spawn({
+            let client = client.clone();
+            async move { client.send_request("test", None).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        task::spawn_blocking(move || {
+            for id in 1..=3 {
+                writeln!(writer, r#"{{"jsonrpc":"2.0","id":{},"result":{{}}}}"#, id).unwrap();
+            }
+        });
+        let response = answered.await.unwrap().unwrap();
+        assert_eq!(response.id, serde_json::json!(3));
+        assert!(client.pending_requests.lock().unwrap().waiting.is_empty());
+    }
+
+    #[tokio::test]
+    async fn duplicate_and_late_responses_are_dropped() {
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        let client = Arc::new(
+            AcpClient::from_io(std::io::sink(), reader, DEFAULT_NOTIFICATION_CAPACITY)
+                .with_request_timeout(Duration::from_millis(100)),
+        );
+        let respond = |writer: &mut std::io::PipeWriter, id: u64, answer: &str| {
+            writeln!(
+                writer,
+                r#"{{"jsonrpc":"2.0","id":{},"result":{{"answer":"{}"}}}}"#,
+                id, answer
+            )
+            .unwrap();
+        };
+
+        // Request 1 is answered, request 2 times out
+        let answered = tokio::spawn({
+            let client = client.clone();
+            async move { client.send_request("test", None).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        respond(&mut writer, 1, "first");
+        answered.await.unwrap().unwrap();
+        let error = client.send_request("test", None).await.unwrap_err();
+        assert!(error.downcast_ref::<AcpRequestTimeout>().is_some());
+
+        // The agent repeats its answer to 1 and answers 2 too late; request 3
+        // still gets its own answer
+        let third = tokio::spawn({
+            let client = client.clone();
+            async move { client.send_request("test", None).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        respond(&mut writer, 1, "again");
+        respond(&mut writer, 2, "late");
+        respond(&mut writer, 3, "third");
+        let response = third.await.unwrap().unwrap();
+        assert_eq!(response.id, serde_json::json!(3));
+        assert_eq!(response.result.unwrap()["answer"], "third");
+
+        let pending = client.pending_requests.lock().unwrap();
+        assert!(pending.waiting.is_empty());
+        assert!((1..=3).all(|id| pending.was_completed(&id.to_string())));
+    }
+
+    /// Stdin of an agent that never reads it: every write blocks until the
+    /// test drops the sender.
+    struct StalledInput(std::sync::mpsc::Receiver<()>);
+
+    impl Write for StalledInput {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            let _ = self.0.recv();
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn a_full_write_queue_fails_the_request() {
+        let (unblock, blocked) = std::sync::mpsc::channel();
+        let (reader, _writer) = std::io::pipe().unwrap();
+        let client =
+            AcpClient::from_io(StalledInput(blocked), reader, DEFAULT_NOTIFICATION_CAPACITY)
+                .with_write_queue_timeout(Duration::from_millis(100));
+
+        // One message stuck in the writer, then the queue fills up
+        for _ in 0..=WRITE_QUEUE_CAPACITY {
+            client.send_notification("test", None).await.unwrap();
+        }
+        let started = Instant::now();
+        let error = client
+            .send_request("session/prompt", None)
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        let backpressure = error.downcast_ref::<AgentInputBackpressure>().unwrap();
+        assert_eq!(backpressure.method, "session/prompt");
+        assert!(client.pending_requests.lock().unwrap().waiting.is_empty());
+        drop(unblock);
+    }
+
+    /// A client for an agent running the shell `script`, whose requests time
+    /// out after `timeout` of silence.
+    fn scripted(script: &str, timeout: Duration) -> AcpClient {
+        let child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        AcpClient::new(child).unwrap().with_request_timeout(timeout)
+    }
+
+    #[tokio::test]
+    async fn an_agent_that_keeps_talking_is_waited_for_past_the_timeout() {
+        let client = scripted(
+            r#"read -r line
+            for i in 1 2 3 4 5 6; do
+              printf '{"jsonrpc":"2.0","method":"session/update","params":{}}\n'
+              sleep 0.1
+            done
+            printf '{"jsonrpc":"2.0","id":1,"result":{}}\n'"#,
+            Duration::from_millis(300),
+        );
+        let started = Instant::now();
+
+        let response = client.send_request("session/prompt", None).await.unwrap();
+        assert_eq!(response.result, Some(serde_json::json!({})));
+        assert!(started.elapsed() >= Duration::from_millis(600));
+    }
+
+    #[tokio::test]
+    async fn requests_to_a_silent_agent_time_out() {
+        let client = scripted("exec sleep 30", Duration::from_millis(200));
+        let started = Instant::now();
+
+        let error = client
+            .send_request("session/prompt", None)
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        let timeout = error.downcast_ref::<AcpRequestTimeout>().unwrap();
+        assert_eq!(timeout.method, "session/prompt");
+        assert_eq!(
+            error.to_string(),
+            "ACP request 'session/prompt' timed out: no word from the agent for 200ms"
+        );
+        {
+            let pending = client.pending_requests.lock().unwrap();
+            assert!(pending.waiting.is_empty());
+            assert!(pending.was_completed("1"));
+        }
+        client.kill().unwrap();
+    }
+
+    #[tokio::test]
+    async fn an_agent_still_sending_updates_is_waited_for() {
+        let client = scripted(
+            r#"for i in 1 2 3 4 5 6; do
+                 echo '{"jsonrpc":"2.0","method":"session/update","params":{}}'
+                 sleep 0.1
+               done
+               echo '{"jsonrpc":"2.0","id":1,"result":{}}'
+               exec sleep 30"#,
+            Duration::from_millis(300),
+        );
+
+        let response = client.send_request("session/prompt", None).await.unwrap();
+        assert_eq!(response.id, serde_json::json!(1));
+        assert!(client.pending_requests.lock().unwrap().waiting.is_empty());
+        client.kill().unwrap();
+    }
 }
@@ -0,0 +1,94 @@
+//! Spotting project files mentioned in a prompt, so their contents can be
+//! attached to it (`prompt_file_references`).
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Most files attached to a single prompt; later mentions are ignored.
+pub const MAX_FILE_REFERENCES: usize = 3;
+
+/// Relative paths in `text` that look like files of the project, in order of
+/// first mention. Deliberately conservative: a candidate is either quoted in
+/// backticks or contains a `/`, and its last component has an extension.
+/// URLs, absolute paths and paths with `..` are left alone.
+pub fn file_references(text: &str) -> Vec<String> {
+    static TOKEN: OnceLock<Regex> = OnceLock::new();
+    let token = TOKEN.get_or_init(|| Regex::new(r"`([^`\s]+)`|(\S+)").unwrap());
+
+    let mut found: Vec<String> = Vec::new();
+    for caps in token.captures_iter(text) {
+        let (candidate, quoted) = match (caps.get(1), caps.get(2)) {
+            (Some(m), _) => (m.as_str(), true),
+            (None, Some(m)) => (m.as_str(), false),
+            _ => continue,
+        };
+        let candidate = candidate
+            .trim_start_matches(['(', '[', '"', '\''])
+            .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '"', '\'']);
+        let candidate = candidate.strip_prefix("./").unwrap_or(candidate);
+
+        if !looks_like_file(candidate, quoted) || found.iter().any(|f| f == candidate) {
+            continue;
+        }
+        found.push(candidate.to_string());
+        if found.len() == MAX_FILE_REFERENCES {
+            break;
+        }
+    }
+    found
+}
+
+fn looks_like_file(candidate: &str, quoted: bool) -> bool {
+    if candidate.is_empty()
+        || candidate.contains("://")
+        || candidate.starts_with('/')
+        || candidate.starts_with('~')
+        || candidate.split('/').any(|part| part == "..")
+    {
+        return false;
+    }
+    if !quoted && !candidate.contains('/') {
+        return false;
+    }
+    let name = candidate.rsplit('/').next().unwrap_or_default();
+    let Some((stem, ext)) = name.rsplit_once('.') else {
+        return false;
+    };
+    !stem.is_empty()
+        && !ext.is_empty()
+        && ext.len() <= 10
+        && ext.chars().all(|c| c.is_ascii_alphanumeric())
+        && candidate
+            .chars()
+            .all(|c| c.is_alphanumeric() || "/._-+@".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_and_quoted_names_are_found_in_order() {
+        assert_eq!(
+            file_references("Fix `main.rs`, then src/lib.rs and ./docs/guide.md."),
+            ["main.rs", "src/lib.rs", "docs/guide.md"]
+        );
+    }
+
+    #[test]
+    fn bare_words_urls_and_paths_leaving_the_project_are_ignored() {
+        assert!(file_references(
+            "see main.rs, https://example.com/a.html, /etc/passwd.txt, \
+             ../secret.txt, ~/notes.md and src/Makefile"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn repeated_mentions_count_once_and_the_count_is_capped() {
+        assert_eq!(
+            file_references("a/1.rs a/1.rs a/2.rs (a/3.rs) a/4.rs"),
+            ["a/1.rs", "a/2.rs", "a/3.rs"]
+        );
+    }
+}
@@ -1,3 +1,7 @@
 pub mod acp;
 pub mod bridge;
+pub mod circuit;
 pub mod client;
+pub mod file_refs;
+pub mod redact;
+pub mod sequence;
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+/// Puts numbered reply chunks back in order. Chunks are released as soon as
+/// every chunk before them has been; one that skips ahead is held until the
+/// gap fills or the caller gives up on it with [`ChunkSequencer::flush`].
+/// The first chunk seen after a reset sets where numbering starts.
+pub struct ChunkSequencer<T> {
+    next: Option<u64>,
+    pending: BTreeMap<u64, T>,
+    // When the oldest unfilled gap opened
+    gap_since: Option<Instant>,
+}
+
+impl<T> Default for ChunkSequencer<T> {
+    fn default() -> Self {
+        Self {
+            next: None,
+            pending: BTreeMap::new(),
+            gap_since: None,
+        }
+    }
+}
+
+impl<T> ChunkSequencer<T> {
+    /// Accept chunk number `seq`, returning the chunks now ready, in order.
+    /// Chunks already released (e.g. resent after a reconnect) are dropped.
+    pub fn push(&mut self, seq: u64, chunk: T) -> Vec<T> {
+        let next = *self.next.get_or_insert(seq);
+        if seq < next || self.pending.contains_key(&seq) {
+            return Vec::new();
+        }
+        self.pending.insert(seq, chunk);
+
+        let mut ready = Vec::new();
+        let mut next = next;
+        while let Some(chunk) = self.pending.remove(&next) {
+            ready.push(chunk);
+            next += 1;
+        }
+        self.next = Some(next);
+
+        self.gap_since = match self.gap_since {
+            _ if self.pending.is_empty() => None,
+            Some(since) if ready.is_empty() => Some(since),
+            _ => Some(Instant::now()),
+        };
+        ready
+    }
+
+    /// When the gap currently holding chunks back opened, if there is one.
+    pub fn waiting_since(&self) -> Option<Instant> {
+        self.gap_since
+    }
+
+    /// Give up on missing chunks: everything held back, in order.
+    pub fn flush(&mut self) -> Vec<T> {
+        let pending = std::mem::take(&mut self.pending);
+        if let Some(last) = pending.keys().next_back() {
+            self.next = Some(last + 1);
+        }
+        self.gap_since = None;
+        pending.into_values().collect()
+    }
+
+    /// Forget everything, e.g. at the start of a turn.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_order_chunks_come_out_in_sequence() {
+        let mut order = ChunkSequencer::default();
+        assert_eq!(order.push(7, "a"), ["a"]);
+        assert_eq!(order.push(9, "c"), Vec::<&str>::new());
+        assert_eq!(order.push(10, "d"), Vec::<&str>::new());
+        assert!(order.waiting_since().is_some());
+        assert_eq!(order.push(8, "b"), ["b", "c", "d"]);
+        assert!(order.waiting_since().is_none());
+        assert_eq!(order.push(11, "e"), ["e"]);
+    }
+
+    #[test]
+    fn resent_chunks_are_dropped() {
+        let mut order = ChunkSequencer::default();
+        assert_eq!(order.push(0, "a"), ["a"]);
+        assert_eq!(order.push(2, "c"), Vec::<&str>::new());
+        assert_eq!(order.push(0, "a again"), Vec::<&str>::new());
+        assert_eq!(order.push(2, "c again"), Vec::<&str>::new());
+        assert_eq!(order.push(1, "b"), ["b", "c"]);
+    }
+
+    #[test]
+    fn flushing_gives_up_on_the_gap() {
+        let mut order = ChunkSequencer::default();
+        order.push(0, "a");
+        order.push(3, "d");
+        order.push(2, "c");
+        let since = order.waiting_since().unwrap();
+        order.push(5, "f");
+        // A later chunk that fills nothing leaves the gap as old as it was
+        assert_eq!(order.waiting_since(), Some(since));
+
+        assert_eq!(order.flush(), ["c", "d", "f"]);
+        assert!(order.waiting_since().is_none());
+        // The missing chunk turning up late is too late
+        assert_eq!(order.push(1, "b"), Vec::<&str>::new());
+        assert_eq!(order.push(6, "g"), ["g"]);
+    }
+
+    #[test]
+    fn a_reset_starts_numbering_afresh() {
+        let mut order = ChunkSequencer::default();
+        order.push(4, "a");
+        order.push(6, "c");
+        order.reset();
+        assert!(order.waiting_since().is_none());
+        assert_eq!(order.push(0, "x"), ["x"]);
+        assert_eq!(order.flush(), Vec::<&str>::new());
+    }
+}
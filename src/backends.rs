@@ -0,0 +1,336 @@
+//! Clients for external AI services (speech-to-text, text completion), behind
+//! traits so interfaces don't make their own HTTP calls and can be given a
+//! different provider, or [`NoopBackend`], without changes. Telegram
+//! transcribes voice messages and writes `/summarize` summaries with them.
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use std::sync::Arc;
+
+use crate::config::AiConfig;
+
+pub const DEFAULT_AI_BASE_URL: &str = "https://api.openai.com/v1";
+pub const DEFAULT_COMPLETION_MODEL: &str = "gpt-4o-mini";
+pub const DEFAULT_TRANSCRIPTION_MODEL: &str = "whisper-1";
+
+/// Turns recorded audio into text.
+pub trait TranscriptionBackend: Send + Sync {
+    /// `filename` is only a hint for the audio format (e.g. `voice.ogg`).
+    fn transcribe<'a>(&'a self, audio: Vec<u8>, filename: &'a str)
+        -> BoxFuture<'a, Result<String>>;
+}
+
+/// Answers a single prompt, optionally steered by a system prompt.
+pub trait CompletionBackend: Send + Sync {
+    fn complete<'a>(
+        &'a self,
+        system: Option<&'a str>,
+        prompt: &'a str,
+    ) -> BoxFuture<'a, Result<String>>;
+}
+
+/// Returned by [`NoopBackend`] for every call.
+#[derive(Debug)]
+pub struct BackendNotConfigured;
+
+impl std::fmt::Display for BackendNotConfigured {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No AI backend configured: set THALASSA_AI_API_KEY")
+    }
+}
+
+impl std::error::Error for BackendNotConfigured {}
+
+/// Stands in when no provider is configured; every call fails with
+/// [`BackendNotConfigured`].
+pub struct NoopBackend;
+
+impl TranscriptionBackend for NoopBackend {
+    fn transcribe<'a>(
+        &'a self,
+        _audio: Vec<u8>,
+        _filename: &'a str,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async { Err(BackendNotConfigured.into()) })
+    }
+}
+
+impl CompletionBackend for NoopBackend {
+    fn complete<'a>(
+        &'a self,
+        _system: Option<&'a str>,
+        _prompt: &'a str,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async { Err(BackendNotConfigured.into()) })
+    }
+}
+
+/// Talks to an OpenAI-compatible API (`/chat/completions`, `/audio/transcriptions`).
+pub struct HttpBackend {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    completion_model: String,
+    transcription_model: String,
+}
+
+impl HttpBackend {
+    /// A client for the configured API, or `None` without an API key.
+    pub fn from_config(config: &AiConfig) -> Option<Self> {
+        let api_key = config.api_key.clone()?;
+        let mut backend = Self::new(&config.base_url, api_key);
+        backend.completion_model = config.model.clone();
+        backend.transcription_model = config.transcription_model.clone();
+        Some(backend)
+    }
+
+    /// A client for the API at `base_url`, using the default models.
+    pub fn new(base_url: &str, api_key: String) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            completion_model: DEFAULT_COMPLETION_MODEL.to_string(),
+            transcription_model: DEFAULT_TRANSCRIPTION_MODEL.to_string(),
+        }
+    }
+
+    async fn post(
+        &self,
+        path: &str,
+        request: reqwest::RequestBuilder,
+    ) -> Result<serde_json::Value> {
+        let resp = request
+            .bearer_auth(&self.api_key)
+            .send()
+            .await
+            .with_context(|| format!("Request to {} failed", path))?;
+        let status = resp.status();
+        let body: serde_json::Value = resp
+            .json()
+            .await
+            .with_context(|| format!("Invalid response from {}", path))?;
+        if !status.is_success() {
+            anyhow::bail!(
+                "{} failed ({}): {}",
+                path,
+                status,
+                body.pointer("/error/message")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown error")
+            );
+        }
+        Ok(body)
+    }
+}
+
+impl TranscriptionBackend for HttpBackend {
+    fn transcribe<'a>(
+        &'a self,
+        audio: Vec<u8>,
+        filename: &'a str,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let form = reqwest::multipart::Form::new()
+                .text("model", self.transcription_model.clone())
+                .part(
+                    "file",
+                    reqwest::multipart::Part::bytes(audio).file_name(filename.to_string()),
+                );
+            let path = "/audio/transcriptions";
+            let body = self
+                .post(
+                    path,
+                    self.http
+                        .post(format!("{}{}", self.base_url, path))
+                        .multipart(form),
+                )
+                .await?;
+            body.get("text")
+                .and_then(|v| v.as_str())
+                .map(|text| text.trim().to_string())
+                .context("Transcription response has no text")
+        })
+    }
+}
+
+impl CompletionBackend for HttpBackend {
+    fn complete<'a>(
+        &'a self,
+        system: Option<&'a str>,
+        prompt: &'a str,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let mut messages = Vec::new();
+            if let Some(system) = system {
+                messages.push(serde_json::json!({ "role": "system", "content": system }));
+            }
+            messages.push(serde_json::json!({ "role": "user", "content": prompt }));
+
+            let path = "/chat/completions";
+            let body = self
+                .post(
+                    path,
+                    self.http
+                        .post(format!("{}{}", self.base_url, path))
+                        .json(&serde_json::json!({
+                            "model": self.completion_model,
+                            "messages": messages,
+                        })),
+                )
+                .await?;
+            body.pointer("/choices/0/message/content")
+                .and_then(|v| v.as_str())
+                .map(|text| text.trim().to_string())
+                .context("Completion response has no content")
+        })
+    }
+}
+
+/// The configured providers; both are [`NoopBackend`] unless `ai` configures an API.
+#[derive(Clone)]
+pub struct Backends {
+    pub transcription: Arc<dyn TranscriptionBackend>,
+    pub completion: Arc<dyn CompletionBackend>,
+}
+
+impl Backends {
+    pub fn from_config(config: &AiConfig) -> Self {
+        match HttpBackend::from_config(config) {
+            Some(http) => {
+                let http = Arc::new(http);
+                Self {
+                    transcription: http.clone(),
+                    completion: http,
+                }
+            }
+            None => Self {
+                transcription: Arc::new(NoopBackend),
+                completion: Arc::new(NoopBackend),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Bytes, http::HeaderMap, http::StatusCode, routing::post, Json, Router};
+    use serde_json::{json, Value};
+
+    fn authorized(headers: &HeaderMap) -> bool {
+        headers.get("authorization").and_then(|v| v.to_str().ok()) == Some("Bearer test-key")
+    }
+
+    /// An OpenAI-compatible API on a local port: completions echo the
+    /// conversation back, transcriptions check the shape of the upload.
+    async fn mock_api() -> String {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(|headers: HeaderMap, Json(body): Json<Value>| async move {
+                    if !authorized(&headers) {
+                        return (
+                            StatusCode::UNAUTHORIZED,
+                            Json(json!({ "error": { "message": "bad key" } })),
+                        );
+                    }
+                    let roles: Vec<&str> = body["messages"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|m| m["role"].as_str().unwrap())
+                        .collect();
+                    let content = format!(
+                        " {} via {}: {} ",
+                        roles.join("+"),
+                        body["model"].as_str().unwrap(),
+                        body["messages"].as_array().unwrap().last().unwrap()["content"]
+                            .as_str()
+                            .unwrap()
+                    );
+                    (
+                        StatusCode::OK,
+                        Json(json!({ "choices": [{ "message": { "content": content } }] })),
+                    )
+                }),
+            )
+            .route(
+                "/v1/audio/transcriptions",
+                post(|headers: HeaderMap, body: Bytes| async move {
+                    let content_type = headers["content-type"].to_str().unwrap().to_string();
+                    let body = String::from_utf8_lossy(&body).into_owned();
+                    let parts = ["name=\"model\"", "whisper-1", "filename=\"voice.ogg\""];
+                    let text = if content_type.starts_with("multipart/form-data")
+                        && parts.iter().all(|part| body.contains(part))
+                    {
+                        "transcribed"
+                    } else {
+                        "malformed upload"
+                    };
+                    Json(json!({ "text": text }))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/v1/", addr)
+    }
+
+    #[tokio::test]
+    async fn completions_send_the_system_and_user_prompts() {
+        let backend = HttpBackend::new(&mock_api().await, "test-key".to_string());
+
+        let answer = backend.complete(Some("be brief"), "hello").await.unwrap();
+        assert_eq!(answer, "system+user via gpt-4o-mini: hello");
+
+        let answer = backend.complete(None, "hi").await.unwrap();
+        assert_eq!(answer, "user via gpt-4o-mini: hi");
+    }
+
+    #[tokio::test]
+    async fn transcriptions_upload_the_audio() {
+        let backend = HttpBackend::new(&mock_api().await, "test-key".to_string());
+        let text = backend.transcribe(vec![0; 42], "voice.ogg").await.unwrap();
+        assert_eq!(text, "transcribed");
+    }
+
+    #[tokio::test]
+    async fn api_errors_carry_the_status_and_message() {
+        let backend = HttpBackend::new(&mock_api().await, "wrong-key".to_string());
+        let err = backend.complete(None, "hello").await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/chat/completions failed (401 Unauthorized): bad key"
+        );
+    }
+
+    #[tokio::test]
+    async fn backends_use_the_configured_api_and_models() {
+        let config = AiConfig {
+            api_key: Some("test-key".to_string()),
+            base_url: mock_api().await,
+            model: "small-model".to_string(),
+            ..AiConfig::default()
+        };
+        let backends = Backends::from_config(&config);
+        let answer = backends.completion.complete(None, "hi").await.unwrap();
+        assert_eq!(answer, "user via small-model: hi");
+
+        let backends = Backends::from_config(&AiConfig::default());
+        let err = backends.completion.complete(None, "hi").await.unwrap_err();
+        assert!(err.downcast_ref::<BackendNotConfigured>().is_some());
+    }
+
+    #[tokio::test]
+    async fn the_noop_backend_says_it_is_not_configured() {
+        let backend = NoopBackend;
+        let err = backend.complete(None, "hello").await.unwrap_err();
+        assert!(err.downcast_ref::<BackendNotConfigured>().is_some());
+        let err = backend
+            .transcribe(Vec::new(), "voice.ogg")
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<BackendNotConfigured>().is_some());
+    }
+}
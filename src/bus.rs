@@ -1,6 +1,9 @@
 use crate::chat::ChatMessage;
 use crate::entity::EntityId;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 use tokio::sync::broadcast;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,19 +19,94 @@ pub enum Event {
         target: Option<EntityId>, // If None, broadcast to everyone
     },
 
-    /// A scheduled job triggered
+    /// An agent session moved to a new lifecycle state
+    AgentStateChanged { project: String, state: AgentState },
+
+    /// An operator announcement for every chat on every interface
+    Announcement { message: String },
+
+    /// A scheduled job triggered. `payload` is a JSON-encoded [`ScheduledPrompt`].
     ScheduledEvent { job_id: String, payload: String },
 
     /// Configuration changed
     ConfigChanged,
 }
 
+/// Lifecycle of a project's agent session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AgentState {
+    /// Agent process spawned, ACP handshake in progress
+    Starting,
+    /// Handshake done, no prompt has run yet
+    Ready,
+    /// A prompt turn is running
+    Busy,
+    /// Last turn finished, waiting for the next prompt
+    Idle,
+    /// The session was torn down
+    Stopped,
+    /// The agent could not be started or lost its ACP session
+    Failed,
+}
+
+impl fmt::Display for AgentState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AgentState::Starting => "starting",
+            AgentState::Ready => "ready",
+            AgentState::Busy => "busy",
+            AgentState::Idle => "idle",
+            AgentState::Stopped => "stopped",
+            AgentState::Failed => "failed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Payload of a scheduled job: send `prompt` to `project`'s agent.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledPrompt {
+    pub project: String,
+    pub prompt: String,
+    /// Routing metadata copied onto the prompt so the agent's reply reaches a chat,
+    /// e.g. `{"recipient": "tg:12345"}` or `{"recipient": "slack:C0123"}`
+    #[serde(default)]
+    pub reply_to: HashMap<String, String>,
+}
+
+/// Ordered by severity so interfaces can filter with `level >= minimum`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum NotificationLevel {
     Info,
+    Success,
     Warning,
     Error,
-    Success,
+}
+
+impl fmt::Display for NotificationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            NotificationLevel::Info => "info",
+            NotificationLevel::Success => "success",
+            NotificationLevel::Warning => "warning",
+            NotificationLevel::Error => "error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for NotificationLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "info" => Ok(NotificationLevel::Info),
+            "success" => Ok(NotificationLevel::Success),
+            "warning" | "warn" => Ok(NotificationLevel::Warning),
+            "error" => Ok(NotificationLevel::Error),
+            other => anyhow::bail!("Unknown notification level: {}", other),
+        }
+    }
 }
 
 pub struct EventBus {
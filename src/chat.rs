@@ -14,6 +14,78 @@ pub struct ChatMessage {
     pub metadata: HashMap<String, String>,
 }
 
+/// Metadata key carrying a message's attachments as a JSON array of [`Attachment`]s.
+pub const ATTACHMENTS_KEY: &str = "attachments";
+
+/// Metadata key marking a status notice (e.g. "queued") rather than an agent's answer.
+pub const NOTICE_KEY: &str = "notice";
+
+/// Metadata key naming the project whose agent a message is for or from.
+pub const PROJECT_NAME_KEY: &str = "project_name";
+
+/// Metadata key with the model the sender wants the agent to answer with.
+pub const MODEL_KEY: &str = "model";
+
+/// Metadata key with the language the sender wants the agent to reply in.
+pub const LANGUAGE_KEY: &str = "language";
+
+/// Metadata key with the id of the prompt an agent reply answers.
+pub const REPLY_TO_KEY: &str = "reply_to";
+
+/// Metadata key with how long the agent took on a reply, in milliseconds.
+pub const AGENT_DURATION_MS_KEY: &str = "agent_duration_ms";
+
+impl ChatMessage {
+    /// Project whose agent this message is for or from, if set.
+    pub fn project_name(&self) -> Option<&str> {
+        self.metadata.get(PROJECT_NAME_KEY).map(String::as_str)
+    }
+
+    /// Whether `project`'s agent should take this message: true unless it
+    /// names another project.
+    pub fn is_for_project(&self, project: &str) -> bool {
+        self.project_name().is_none_or(|p| p == project)
+    }
+
+    /// How long the agent took on this reply, if recorded.
+    pub fn agent_duration(&self) -> Option<std::time::Duration> {
+        self.metadata
+            .get(AGENT_DURATION_MS_KEY)
+            .and_then(|ms| ms.parse().ok())
+            .map(std::time::Duration::from_millis)
+    }
+
+    /// Whether this is a status notice instead of a real reply.
+    pub fn is_notice(&self) -> bool {
+        self.metadata.contains_key(NOTICE_KEY)
+    }
+
+    /// Files attached to this message, if any.
+    pub fn attachments(&self) -> Vec<Attachment> {
+        self.metadata
+            .get(ATTACHMENTS_KEY)
+            .and_then(|raw| serde_json::from_str(raw).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// A file produced by an agent, referenced by its path inside the project container.
+/// Interfaces decide how to present it (photo, document, link, ...).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub path: String,
+    pub name: String,
+    pub mime_type: Option<String>,
+}
+
+impl Attachment {
+    pub fn is_image(&self) -> bool {
+        self.mime_type
+            .as_deref()
+            .is_some_and(|mime| mime.starts_with("image/"))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatSession {
     pub id: String,
@@ -35,3 +107,77 @@ impl ChatSession {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::Role;
+
+    fn prompt(project: Option<&str>) -> ChatMessage {
+        let mut metadata = HashMap::new();
+        if let Some(project) = project {
+            metadata.insert(PROJECT_NAME_KEY.to_string(), project.to_string());
+        }
+        ChatMessage {
+            id: "msg-1".to_string(),
+            chat_id: Some("telegram:1".to_string()),
+            sender: EntityId::new("user-1", "alice", Role::User),
+            content: "hello".to_string(),
+            timestamp: Utc::now(),
+            metadata,
+        }
+    }
+
+    #[test]
+    fn prompts_without_a_project_are_for_every_project() {
+        assert!(prompt(Some("web")).is_for_project("web"));
+        assert!(!prompt(Some("web")).is_for_project("docs"));
+        assert!(prompt(None).is_for_project("web"));
+        assert!(prompt(None).is_for_project("docs"));
+    }
+
+    fn with_metadata(entries: &[(&str, &str)]) -> ChatMessage {
+        let mut msg = prompt(None);
+        for (key, value) in entries {
+            msg.metadata.insert(key.to_string(), value.to_string());
+        }
+        msg
+    }
+
+    #[test]
+    fn typed_getters_read_their_keys() {
+        let msg = with_metadata(&[
+            (PROJECT_NAME_KEY, "web"),
+            (AGENT_DURATION_MS_KEY, "1500"),
+            (NOTICE_KEY, "queued"),
+            (
+                ATTACHMENTS_KEY,
+                r#"[{"path":"/workspace/web/plot.png","name":"plot.png","mime_type":"image/png"}]"#,
+            ),
+        ]);
+
+        assert_eq!(msg.project_name(), Some("web"));
+        assert_eq!(
+            msg.agent_duration(),
+            Some(std::time::Duration::from_millis(1500))
+        );
+        assert!(msg.is_notice());
+        let attachments = msg.attachments();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].name, "plot.png");
+        assert!(attachments[0].is_image());
+    }
+
+    #[test]
+    fn typed_getters_treat_missing_or_malformed_values_as_unset() {
+        let msg = prompt(None);
+        assert_eq!(msg.project_name(), None);
+        assert_eq!(msg.agent_duration(), None);
+        assert!(!msg.is_notice());
+        assert!(msg.attachments().is_empty());
+
+        let msg = with_metadata(&[(AGENT_DURATION_MS_KEY, "soon"), (ATTACHMENTS_KEY, "{")]);
+        assert_eq!(msg.agent_duration(), None);
+        assert!(msg.attachments().is_empty());
+    }
+}
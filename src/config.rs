@@ -0,0 +1,806 @@
+use crate::agent::bridge::PromptOverlap;
+use crate::agent::client::RequestIdStrategy;
+use crate::backends::{DEFAULT_AI_BASE_URL, DEFAULT_COMPLETION_MODEL, DEFAULT_TRANSCRIPTION_MODEL};
+use crate::bus::NotificationLevel;
+use crate::mcp::server::{
+    RequestLimits, DEFAULT_MAX_ARGUMENT_BYTES, DEFAULT_MAX_BODY_BYTES, DEFAULT_MAX_JSON_DEPTH,
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Shown in place of a configured secret by [`Config::redacted_summary`].
+const REDACTED_SECRET: &str = "***";
+
+/// Daemon configuration, loaded from an optional TOML file.
+/// Environment variables override values from the file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub bind_address: String,
+    pub port: u16,
+    pub db_path: PathBuf,
+    /// Directory holding project checkouts inside each container
+    pub projects_dir: String,
+    /// Command run inside the container to start an ACP agent
+    pub agent_command: String,
+    pub max_body_bytes: usize,
+    /// Deepest JSON nesting accepted in an MCP request
+    pub max_json_depth: usize,
+    /// Largest `arguments` object accepted in an MCP tool call
+    pub max_argument_bytes: usize,
+    /// Cap on a single agent reply; anything beyond is dropped and the turn cancelled
+    pub max_response_bytes: usize,
+    /// Largest file the `read_file` and `write_file` MCP tools move in one call,
+    /// and that `prompt_file_references` attaches to a prompt
+    pub max_file_bytes: u64,
+    /// Projects launched at startup so their agents are ready before the first `/enter`
+    pub startup_projects: Vec<String>,
+    /// How many project launches (container + agent boot) may run at once
+    pub max_concurrent_launches: usize,
+    /// Models users may switch the agent to with `/model`; empty disables switching
+    pub allowed_models: Vec<String>,
+    /// Language agents are told to reply in, unless a chat picks its own with `/lang`
+    pub response_language: Option<String>,
+    /// How long the project list from the runtime is reused before asking again
+    pub project_cache_ttl_secs: u64,
+    /// How long a container runtime health check is reused before pinging again
+    pub runtime_health_ttl_secs: u64,
+    /// Host directory holding the projects; watched so added or removed projects
+    /// show up (and are announced) without waiting out the project cache
+    pub projects_watch_dir: Option<PathBuf>,
+    /// JSON-RPC id scheme for requests to agents: "sequential" or "uuid"
+    pub acp_request_ids: RequestIdStrategy,
+    /// How long a request to an agent may wait for its response while the
+    /// agent sends nothing at all; 0 waits forever
+    pub acp_request_timeout_secs: u64,
+    /// Prompt arriving mid-turn: "queue" behind the running turn, "cancel" it or "reject" the prompt
+    pub prompt_overlap: PromptOverlap,
+    /// Send agents a throwaway prompt before reporting them ready, for slow-starting backends
+    pub agent_warmup: bool,
+    /// Post a progress notice every this many seconds while a turn runs; 0 disables
+    pub progress_nudge_secs: u64,
+    /// Post each tool the agent calls to the chat as it happens
+    pub show_tool_calls: bool,
+    /// Prompt failures in a row after which an agent is given a rest; 0 disables
+    pub circuit_breaker_failures: u32,
+    /// Failures further apart than this from the first one start a new count
+    pub circuit_breaker_window_secs: u64,
+    /// How long prompts are turned away before the agent gets another try
+    pub circuit_breaker_cooldown_secs: u64,
+    /// How many of a chat's latest messages `/summarize` hands the agent
+    pub summary_messages: usize,
+    /// Earlier messages of the chat sent along with each prompt, for agents
+    /// without their own memory; 0 disables
+    pub prompt_context_messages: usize,
+    /// Cap on that history; the oldest messages are left out to fit
+    pub prompt_context_bytes: usize,
+    /// Attach files of the project that a prompt mentions by relative path
+    /// (e.g. `src/main.rs`), so the agent sees their contents
+    pub prompt_file_references: bool,
+    /// Projects each user may see and enter, keyed by Telegram user id, Slack
+    /// user id or MCP identity. Entries are project names or `*` patterns.
+    /// Once there are any entries, users without one see nothing.
+    pub project_access: HashMap<String, Vec<String>>,
+    /// Bearer tokens HTTP MCP clients authenticate with, keyed by the identity
+    /// each stands for (as used in `project_access` and `mcp_admins`)
+    pub mcp_tokens: HashMap<String, String>,
+    /// MCP identities that may use admin tools such as `get_config`
+    pub mcp_admins: Vec<String>,
+    /// CPU/memory caps for project containers, keyed by project name
+    pub project_limits: HashMap<String, ResourceLimits>,
+    /// Regexes whose matches in agent replies are replaced with `[REDACTED]`
+    pub redact_patterns: Vec<String>,
+    /// Append every bus event to this JSONL file, for replaying with `--replay`
+    pub record_events: Option<PathBuf>,
+    pub telegram: TelegramConfig,
+    /// Further bots run by the same daemon, e.g. a staging bot next to production
+    pub telegram_bots: Vec<TelegramConfig>,
+    pub slack: SlackConfig,
+    /// HTTPS (and optionally client certificates) for the HTTP server; plain HTTP when unset
+    pub tls: TlsConfig,
+    /// API transcribing voice messages and writing `/summarize` summaries
+    pub ai: AiConfig,
+    /// File this config was loaded from, re-read on `ConfigChanged`
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TelegramConfig {
+    /// Tells bots apart in logs and reply routing; required for `telegram_bots` entries
+    pub name: Option<String>,
+    pub token: Option<String>,
+    pub whitelist: Vec<String>,
+    /// With an empty whitelist, let every user in instead of denying everyone
+    pub allow_all: bool,
+    pub admins: Vec<String>,
+    pub notification_level: NotificationLevel,
+    pub max_upload_bytes: u32,
+    /// Join text messages sent within this many ms of each other into one prompt; 0 disables
+    pub message_debounce_ms: u64,
+    /// Projects this bot offers (`*` patterns); empty offers all of them
+    pub projects: Vec<String>,
+    /// Chats told when the daemon comes online
+    pub notify_chats: Vec<i64>,
+    /// Also tell `notify_chats` when the daemon shuts down gracefully
+    pub notify_offline: bool,
+}
+
+/// Resource caps applied to a project's container once it is running.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// CPU quota in cores, e.g. 1.5
+    pub cpus: Option<f64>,
+    /// Memory cap in docker notation, e.g. "512m" or "2g"; swap is capped to the same
+    pub memory: Option<String>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        self.cpus.is_none() && self.memory.is_none()
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(cpus) = self.cpus {
+            if !cpus.is_finite() || cpus <= 0.0 {
+                anyhow::bail!("cpus must be a positive number, got {}", cpus);
+            }
+        }
+        if let Some(memory) = &self.memory {
+            let lower = memory.trim().to_lowercase();
+            let digits = lower.trim_end_matches(['b', 'k', 'm', 'g']);
+            let valid = !digits.is_empty()
+                && lower.len() - digits.len() <= 1
+                && digits.bytes().all(|b| b.is_ascii_digit())
+                && digits.parse::<u64>().is_ok_and(|n| n > 0);
+            if !valid {
+                anyhow::bail!(
+                    "memory must be a size like \"512m\" or \"2g\", got '{}'",
+                    memory
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ResourceLimits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cpus = self
+            .cpus
+            .map_or("unlimited".to_string(), |c| format!("{} CPUs", c));
+        let memory = self.memory.as_deref().unwrap_or("unlimited");
+        write!(f, "{}, memory {}", cpus, memory)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SlackConfig {
+    pub bot_token: Option<String>,
+    pub signing_secret: Option<String>,
+    /// Slack user ids (e.g. `U0123ABC`) allowed to use the app
+    pub whitelist: Vec<String>,
+    /// With an empty whitelist, let every workspace member in instead of denying everyone
+    pub allow_all: bool,
+}
+
+/// PEM files for serving HTTPS. With `client_ca`, clients must present a
+/// certificate signed by it (mutual TLS).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub client_ca: Option<PathBuf>,
+}
+
+/// An OpenAI-compatible API for [`crate::backends`]. Without an API key voice
+/// messages can't be transcribed and `/summarize` asks the project's agent.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AiConfig {
+    pub api_key: Option<String>,
+    pub base_url: String,
+    /// Model answering completions, e.g. summaries
+    pub model: String,
+    pub transcription_model: String,
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        Self {
+            api_key: None,
+            base_url: DEFAULT_AI_BASE_URL.to_string(),
+            model: DEFAULT_COMPLETION_MODEL.to_string(),
+            transcription_model: DEFAULT_TRANSCRIPTION_MODEL.to_string(),
+        }
+    }
+}
+
+impl TlsConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.cert.is_some()
+    }
+
+    fn validate(&self) -> Result<()> {
+        match (&self.cert, &self.key) {
+            (Some(_), None) | (None, Some(_)) => {
+                anyhow::bail!("tls.cert and tls.key must be set together")
+            }
+            (None, None) if self.client_ca.is_some() => {
+                anyhow::bail!("tls.client_ca needs tls.cert and tls.key")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let home_dir = std::env::var("HOME").unwrap_or_else(|_| ".".into());
+
+        Self {
+            bind_address: "0.0.0.0".to_string(),
+            port: 3000,
+            db_path: Path::new(&home_dir).join(".mothership").join("thalassa.db"),
+            projects_dir: "/home/devuser/projects".to_string(),
+            agent_command: "opencode acp".to_string(),
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            max_json_depth: DEFAULT_MAX_JSON_DEPTH,
+            max_argument_bytes: DEFAULT_MAX_ARGUMENT_BYTES,
+            max_response_bytes: 256 * 1024,
+            max_file_bytes: 1024 * 1024,
+            startup_projects: Vec::new(),
+            max_concurrent_launches: 2,
+            allowed_models: Vec::new(),
+            response_language: None,
+            acp_request_ids: RequestIdStrategy::default(),
+            acp_request_timeout_secs: 120,
+            prompt_overlap: PromptOverlap::default(),
+            agent_warmup: false,
+            progress_nudge_secs: 0,
+            show_tool_calls: false,
+            circuit_breaker_failures: 5,
+            circuit_breaker_window_secs: 300,
+            circuit_breaker_cooldown_secs: 60,
+            summary_messages: 50,
+            prompt_context_messages: 0,
+            prompt_context_bytes: 16 * 1024,
+            prompt_file_references: false,
+            project_cache_ttl_secs: 5,
+            runtime_health_ttl_secs: 5,
+            projects_watch_dir: None,
+            project_access: HashMap::new(),
+            mcp_tokens: HashMap::new(),
+            mcp_admins: Vec::new(),
+            project_limits: HashMap::new(),
+            redact_patterns: Vec::new(),
+            record_events: None,
+            telegram: TelegramConfig::default(),
+            telegram_bots: Vec::new(),
+            slack: SlackConfig::default(),
+            tls: TlsConfig::default(),
+            ai: AiConfig::default(),
+            source: None,
+        }
+    }
+}
+
+impl Default for TelegramConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            token: None,
+            whitelist: Vec::new(),
+            allow_all: false,
+            admins: Vec::new(),
+            notification_level: NotificationLevel::Info,
+            // The Bot API can't serve downloads beyond 20 MiB anyway
+            max_upload_bytes: 20 * 1024 * 1024,
+            message_debounce_ms: 0,
+            projects: Vec::new(),
+            notify_chats: Vec::new(),
+            notify_offline: false,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file at `path` (if any), then apply environment overrides.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut config = match path {
+            Some(path) => {
+                let raw = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config file {}", path.display()))?;
+                toml::from_str(&raw)
+                    .with_context(|| format!("Failed to parse config file {}", path.display()))?
+            }
+            None => Config::default(),
+        };
+
+        config.source = path.map(Path::to_path_buf);
+        config.apply_env()?;
+        config.validate_telegram_bots()?;
+        config.validate_mcp_tokens()?;
+        config.tls.validate()?;
+        for (project, limits) in &config.project_limits {
+            limits
+                .validate()
+                .with_context(|| format!("Invalid project_limits for '{}'", project))?;
+        }
+        Ok(config)
+    }
+
+    /// Limits on MCP request shape, as given to the MCP server.
+    pub fn request_limits(&self) -> RequestLimits {
+        RequestLimits {
+            max_json_depth: self.max_json_depth,
+            max_argument_bytes: self.max_argument_bytes,
+        }
+    }
+
+    /// Every Telegram bot to run: the main `telegram` one (if it has a token),
+    /// then the `telegram_bots` entries.
+    pub fn telegram_bots(&self) -> Vec<TelegramConfig> {
+        let main = self.telegram.token.is_some().then(|| self.telegram.clone());
+        main.into_iter()
+            .chain(self.telegram_bots.iter().cloned())
+            .collect()
+    }
+
+    /// The effective settings for diagnostics. Built field by field rather than
+    /// serialized so a newly added secret can't leak: tokens and secrets only
+    /// show as `***` when set.
+    pub fn redacted_summary(&self) -> serde_json::Value {
+        let secret = |value: &Option<String>| value.as_ref().map(|_| REDACTED_SECRET);
+        let bots: Vec<_> = self
+            .telegram_bots()
+            .iter()
+            .map(|bot| {
+                serde_json::json!({
+                    "name": bot.name,
+                    "token": secret(&bot.token),
+                    "whitelist_size": bot.whitelist.len(),
+                    "allow_all": bot.allow_all,
+                    "admins_size": bot.admins.len(),
+                    "notification_level": bot.notification_level.to_string(),
+                    "max_upload_bytes": bot.max_upload_bytes,
+                    "message_debounce_ms": bot.message_debounce_ms,
+                    "projects": bot.projects,
+                    "notify_chats_size": bot.notify_chats.len(),
+                    "notify_offline": bot.notify_offline,
+                })
+            })
+            .collect();
+        let slack = serde_json::json!({
+            "enabled": self.slack.bot_token.is_some() && self.slack.signing_secret.is_some(),
+            "bot_token": secret(&self.slack.bot_token),
+            "signing_secret": secret(&self.slack.signing_secret),
+            "whitelist_size": self.slack.whitelist.len(),
+            "allow_all": self.slack.allow_all,
+        });
+        let ai = serde_json::json!({
+            "api_key": secret(&self.ai.api_key),
+            "base_url": self.ai.base_url,
+            "model": self.ai.model,
+            "transcription_model": self.ai.transcription_model,
+        });
+
+        serde_json::json!({
+            "source": self.source.as_ref().map(|p| p.display().to_string()),
+            "bind_address": self.bind_address,
+            "port": self.port,
+            "tls": {
+                "enabled": self.tls.is_enabled(),
+                "client_certificates": self.tls.client_ca.is_some(),
+            },
+            "db_path": self.db_path.display().to_string(),
+            "projects_dir": self.projects_dir,
+            "agent_command": self.agent_command,
+            "max_body_bytes": self.max_body_bytes,
+            "max_json_depth": self.max_json_depth,
+            "max_argument_bytes": self.max_argument_bytes,
+            "max_response_bytes": self.max_response_bytes,
+            "max_file_bytes": self.max_file_bytes,
+            "startup_projects": self.startup_projects,
+            "max_concurrent_launches": self.max_concurrent_launches,
+            "allowed_models": self.allowed_models,
+            "response_language": self.response_language,
+            "project_cache_ttl_secs": self.project_cache_ttl_secs,
+            "runtime_health_ttl_secs": self.runtime_health_ttl_secs,
+            "projects_watch_dir": self.projects_watch_dir.as_ref().map(|p| p.display().to_string()),
+            "acp_request_ids": format!("{:?}", self.acp_request_ids).to_lowercase(),
+            "acp_request_timeout_secs": self.acp_request_timeout_secs,
+            "prompt_overlap": format!("{:?}", self.prompt_overlap).to_lowercase(),
+            "agent_warmup": self.agent_warmup,
+            "progress_nudge_secs": self.progress_nudge_secs,
+            "show_tool_calls": self.show_tool_calls,
+            "circuit_breaker_failures": self.circuit_breaker_failures,
+            "circuit_breaker_window_secs": self.circuit_breaker_window_secs,
+            "circuit_breaker_cooldown_secs": self.circuit_breaker_cooldown_secs,
+            "summary_messages": self.summary_messages,
+            "prompt_context_messages": self.prompt_context_messages,
+            "prompt_context_bytes": self.prompt_context_bytes,
+            "prompt_file_references": self.prompt_file_references,
+            "project_access_size": self.project_access.len(),
+            "mcp_tokens_size": self.mcp_tokens.len(),
+            "mcp_admins": self.mcp_admins,
+            "project_limits": self
+                .project_limits
+                .iter()
+                .map(|(project, limits)| (project.clone(), limits.to_string()))
+                .collect::<HashMap<_, _>>(),
+            // Patterns describe what is secret, so only their number is shown
+            "redact_patterns_size": self.redact_patterns.len(),
+            "record_events": self.record_events.as_ref().map(|p| p.display().to_string()),
+            "interfaces": {
+                "telegram": bots,
+                "slack": slack,
+            },
+            "ai": ai,
+        })
+    }
+
+    /// Extra bots need a token and a unique name, which keys their replies.
+    fn validate_telegram_bots(&self) -> Result<()> {
+        let mut names = std::collections::HashSet::new();
+        for bot in &self.telegram_bots {
+            let Some(name) = bot.name.as_deref().filter(|n| !n.trim().is_empty()) else {
+                anyhow::bail!("Every telegram_bots entry needs a name");
+            };
+            if bot.token.is_none() {
+                anyhow::bail!("Telegram bot '{}' has no token", name);
+            }
+            if !names.insert(name) || self.telegram.name.as_deref() == Some(name) {
+                anyhow::bail!("Telegram bot name '{}' is used twice", name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Every MCP token must be usable and name exactly one identity.
+    fn validate_mcp_tokens(&self) -> Result<()> {
+        let mut tokens = std::collections::HashSet::new();
+        for (identity, token) in &self.mcp_tokens {
+            if token.trim().is_empty() {
+                anyhow::bail!("MCP token of '{}' is empty", identity);
+            }
+            if !tokens.insert(token) {
+                anyhow::bail!(
+                    "MCP token of '{}' is also used by another identity",
+                    identity
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The identity whose `mcp_tokens` entry is `token`, if any.
+    pub fn mcp_identity(&self, token: &str) -> Option<&str> {
+        use sha2::{Digest, Sha256};
+        // Compare digests, so how long a guess matches a token doesn't show in timing
+        let presented = Sha256::digest(token.as_bytes());
+        self.mcp_tokens
+            .iter()
+            .find(|(_, known)| Sha256::digest(known.as_bytes()) == presented)
+            .map(|(identity, _)| identity.as_str())
+    }
+
+    fn apply_env(&mut self) -> Result<()> {
+        if let Some(v) = env("THALASSA_BIND_ADDRESS") {
+            self.bind_address = v;
+        }
+        if let Some(v) = env_parse("THALASSA_PORT")? {
+            self.port = v;
+        }
+        if let Some(v) = env("THALASSA_DB_PATH") {
+            self.db_path = PathBuf::from(v);
+        }
+        if let Some(v) = env("THALASSA_PROJECTS_DIR") {
+            self.projects_dir = v;
+        }
+        if let Some(v) = env("THALASSA_AGENT_COMMAND") {
+            self.agent_command = v;
+        }
+        if let Some(v) = env_parse("THALASSA_MAX_BODY_BYTES")? {
+            self.max_body_bytes = v;
+        }
+        if let Some(v) = env_parse("THALASSA_MAX_JSON_DEPTH")? {
+            self.max_json_depth = v;
+        }
+        if let Some(v) = env_parse("THALASSA_MAX_ARGUMENT_BYTES")? {
+            self.max_argument_bytes = v;
+        }
+        if let Some(v) = env_parse("THALASSA_MAX_RESPONSE_BYTES")? {
+            self.max_response_bytes = v;
+        }
+        if let Some(v) = env_parse("THALASSA_MAX_FILE_BYTES")? {
+            self.max_file_bytes = v;
+        }
+        if let Some(v) = env("THALASSA_STARTUP_PROJECTS") {
+            self.startup_projects = split_list(&v);
+        }
+        if let Some(v) = env_parse("THALASSA_MAX_CONCURRENT_LAUNCHES")? {
+            self.max_concurrent_launches = v;
+        }
+        if let Some(v) = env("THALASSA_ALLOWED_MODELS") {
+            self.allowed_models = split_list(&v);
+        }
+        if let Some(v) = env("THALASSA_RESPONSE_LANGUAGE") {
+            self.response_language = Some(v);
+        }
+        if let Some(v) = env_parse("THALASSA_PROJECT_CACHE_TTL_SECS")? {
+            self.project_cache_ttl_secs = v;
+        }
+        if let Some(v) = env_parse("THALASSA_RUNTIME_HEALTH_TTL_SECS")? {
+            self.runtime_health_ttl_secs = v;
+        }
+        if let Some(v) = env_parse("THALASSA_ACP_REQUEST_IDS")? {
+            self.acp_request_ids = v;
+        }
+        if let Some(v) = env_parse("THALASSA_ACP_REQUEST_TIMEOUT_SECS")? {
+            self.acp_request_timeout_secs = v;
+        }
+        if let Some(v) = env_parse("THALASSA_PROMPT_OVERLAP")? {
+            self.prompt_overlap = v;
+        }
+        if let Some(v) = env_parse("THALASSA_AGENT_WARMUP")? {
+            self.agent_warmup = v;
+        }
+        if let Some(v) = env_parse("THALASSA_PROGRESS_NUDGE_SECS")? {
+            self.progress_nudge_secs = v;
+        }
+        if let Some(v) = env_parse("THALASSA_SHOW_TOOL_CALLS")? {
+            self.show_tool_calls = v;
+        }
+        if let Some(v) = env_parse("THALASSA_CIRCUIT_BREAKER_FAILURES")? {
+            self.circuit_breaker_failures = v;
+        }
+        if let Some(v) = env_parse("THALASSA_CIRCUIT_BREAKER_WINDOW_SECS")? {
+            self.circuit_breaker_window_secs = v;
+        }
+        if let Some(v) = env_parse("THALASSA_CIRCUIT_BREAKER_COOLDOWN_SECS")? {
+            self.circuit_breaker_cooldown_secs = v;
+        }
+        if let Some(v) = env_parse("THALASSA_SUMMARY_MESSAGES")? {
+            self.summary_messages = v;
+        }
+        if let Some(v) = env_parse("THALASSA_PROMPT_CONTEXT_MESSAGES")? {
+            self.prompt_context_messages = v;
+        }
+        if let Some(v) = env_parse("THALASSA_PROMPT_CONTEXT_BYTES")? {
+            self.prompt_context_bytes = v;
+        }
+        if let Some(v) = env_parse("THALASSA_PROMPT_FILE_REFERENCES")? {
+            self.prompt_file_references = v;
+        }
+        if let Some(v) = env("THALASSA_PROJECT_LIMITS") {
+            self.project_limits = serde_json::from_str(&v)
+                .map_err(|e| anyhow::anyhow!("Invalid THALASSA_PROJECT_LIMITS: {}", e))?;
+        }
+        if let Some(v) = env("THALASSA_MCP_TOKENS") {
+            self.mcp_tokens = serde_json::from_str(&v)
+                .map_err(|e| anyhow::anyhow!("Invalid THALASSA_MCP_TOKENS: {}", e))?;
+        }
+        if let Some(v) = env("THALASSA_MCP_ADMINS") {
+            self.mcp_admins = split_list(&v);
+        }
+        if let Some(v) = env("THALASSA_PROJECTS_WATCH_DIR") {
+            self.projects_watch_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = env("THALASSA_RECORD_EVENTS") {
+            self.record_events = Some(PathBuf::from(v));
+        }
+        if let Some(v) = env("THALASSA_REDACT_PATTERNS") {
+            self.redact_patterns = serde_json::from_str(&v)
+                .map_err(|e| anyhow::anyhow!("Invalid THALASSA_REDACT_PATTERNS: {}", e))?;
+        }
+
+        if let Some(v) = env("TELOXIDE_TOKEN").or_else(|| env("TELEGRAM_BOT_TOKEN")) {
+            self.telegram.token = Some(v);
+        }
+        if let Some(v) = env("TELEGRAM_WHITELIST") {
+            self.telegram.whitelist = split_list(&v);
+        }
+        if let Some(v) = env_parse("TELEGRAM_ALLOW_ALL")? {
+            self.telegram.allow_all = v;
+        }
+        if let Some(v) = env("TELEGRAM_ADMINS") {
+            self.telegram.admins = split_list(&v);
+        }
+        if let Some(v) = env_parse("TELEGRAM_NOTIFICATION_LEVEL")? {
+            self.telegram.notification_level = v;
+        }
+        if let Some(v) = env_parse("TELEGRAM_MAX_UPLOAD_BYTES")? {
+            self.telegram.max_upload_bytes = v;
+        }
+        if let Some(v) = env_parse("TELEGRAM_MESSAGE_DEBOUNCE_MS")? {
+            self.telegram.message_debounce_ms = v;
+        }
+        if let Some(v) = env("TELEGRAM_NOTIFY_CHATS") {
+            self.telegram.notify_chats = split_list(&v)
+                .iter()
+                .map(|id| id.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|e| anyhow::anyhow!("Invalid TELEGRAM_NOTIFY_CHATS: {}", e))?;
+        }
+        if let Some(v) = env_parse("TELEGRAM_NOTIFY_OFFLINE")? {
+            self.telegram.notify_offline = v;
+        }
+
+        if let Some(v) = env("TELEGRAM_BOTS") {
+            self.telegram_bots = serde_json::from_str(&v)
+                .map_err(|e| anyhow::anyhow!("Invalid TELEGRAM_BOTS: {}", e))?;
+        }
+
+        if let Some(v) = env("SLACK_BOT_TOKEN") {
+            self.slack.bot_token = Some(v);
+        }
+        if let Some(v) = env("SLACK_SIGNING_SECRET") {
+            self.slack.signing_secret = Some(v);
+        }
+        if let Some(v) = env("SLACK_WHITELIST") {
+            self.slack.whitelist = split_list(&v);
+        }
+        if let Some(v) = env_parse("SLACK_ALLOW_ALL")? {
+            self.slack.allow_all = v;
+        }
+
+        if let Some(v) = env("THALASSA_TLS_CERT") {
+            self.tls.cert = Some(PathBuf::from(v));
+        }
+        if let Some(v) = env("THALASSA_TLS_KEY") {
+            self.tls.key = Some(PathBuf::from(v));
+        }
+        if let Some(v) = env("THALASSA_TLS_CLIENT_CA") {
+            self.tls.client_ca = Some(PathBuf::from(v));
+        }
+
+        if let Some(v) = env("THALASSA_AI_API_KEY") {
+            self.ai.api_key = Some(v);
+        }
+        if let Some(v) = env("THALASSA_AI_BASE_URL") {
+            self.ai.base_url = v;
+        }
+        if let Some(v) = env("THALASSA_AI_MODEL") {
+            self.ai.model = v;
+        }
+        if let Some(v) = env("THALASSA_AI_TRANSCRIPTION_MODEL") {
+            self.ai.transcription_model = v;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the user known as `identity` may use `project`. Without any
+    /// `project_access` entries everyone may; with some, only listed users,
+    /// within their patterns.
+    pub fn can_access_project(&self, identity: &str, project: &str) -> bool {
+        if self.project_access.is_empty() {
+            return true;
+        }
+        self.project_access
+            .get(identity)
+            .is_some_and(|patterns| patterns.iter().any(|p| matches_pattern(p, project)))
+    }
+
+    /// Absolute path of a project's checkout inside its container.
+    pub fn project_dir(&self, project_name: &str) -> String {
+        format!(
+            "{}/{}",
+            self.projects_dir.trim_end_matches('/'),
+            project_name
+        )
+    }
+}
+
+fn env(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|v| !v.trim().is_empty())
+}
+
+fn env_parse<T>(key: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    env(key)
+        .map(|v| {
+            v.trim()
+                .parse::<T>()
+                .map_err(|e| anyhow::anyhow!("Invalid {} '{}': {}", key, v, e))
+        })
+        .transpose()
+}
+
+/// Parse a comma-separated list, dropping empty entries.
+fn split_list(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+impl TelegramConfig {
+    /// Whether this bot offers `project` at all.
+    pub fn serves_project(&self, project: &str) -> bool {
+        self.projects.is_empty() || self.projects.iter().any(|p| matches_pattern(p, project))
+    }
+}
+
+/// Match `name` against `pattern`, where `*` stands for any run of characters.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*` at all: exact match
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_access(entries: &[(&str, &[&str])]) -> Config {
+        Config {
+            project_access: entries
+                .iter()
+                .map(|(id, patterns)| {
+                    (
+                        id.to_string(),
+                        patterns.iter().map(|p| p.to_string()).collect(),
+                    )
+                })
+                .collect(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn everyone_can_access_every_project_without_project_access() {
+        let config = with_access(&[]);
+        assert!(config.can_access_project("42", "web-app"));
+        assert!(config.can_access_project("anonymous", "docs"));
+    }
+
+    #[test]
+    fn unlisted_users_are_denied_once_project_access_is_set() {
+        let config = with_access(&[("1001", &["docs"])]);
+        assert!(!config.can_access_project("42", "web-app"));
+        assert!(!config.can_access_project("42", "docs"));
+        assert!(!config.can_access_project("anonymous", "docs"));
+    }
+
+    #[test]
+    fn listed_users_are_limited_to_their_patterns() {
+        let config = with_access(&[("1001", &["web-*", "docs"])]);
+        assert!(config.can_access_project("1001", "web-app"));
+        assert!(config.can_access_project("1001", "docs"));
+        assert!(!config.can_access_project("1001", "docs-old"));
+        assert!(!config.can_access_project("1001", "infra"));
+    }
+
+    #[test]
+    fn an_empty_entry_denies_everything() {
+        let config = with_access(&[("anonymous", &[])]);
+        assert!(!config.can_access_project("anonymous", "docs"));
+    }
+}
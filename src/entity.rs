@@ -13,6 +13,8 @@ pub enum Role {
     System,
     User,
     Agent,
+    /// Tools run by an agent; their messages report the calls as they happen
+    Tool,
 }
 
 impl EntityId {
@@ -45,6 +47,7 @@ pub enum Entity {
     System,
     User(TelegramUser),
     Agent(AgentEntity),
+    Tool(ToolEntity),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,20 +62,58 @@ pub struct AgentEntity {
     pub project_name: String,
 }
 
+/// The tools a project's agent calls, as the sender of tool activity messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolEntity {
+    pub project_name: String,
+}
+
 impl Entity {
+    /// The id messages from this entity carry; also its key in the store.
     pub fn id(&self) -> EntityId {
         match self {
             Entity::System => EntityId::system(),
             Entity::User(u) => EntityId::new(
-                format!("telegram:{}", u.id),
+                u.id.to_string(),
                 u.username.clone().unwrap_or(u.first_name.clone()),
                 Role::User,
             ),
             Entity::Agent(a) => EntityId::new(
-                format!("agent:{}", a.project_name),
+                format!("agent-{}", a.project_name),
                 format!("Agent ({})", a.project_name),
                 Role::Agent,
             ),
+            Entity::Tool(t) => EntityId::new(
+                format!("tool-{}", t.project_name),
+                format!("Tools ({})", t.project_name),
+                Role::Tool,
+            ),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_role_round_trips_through_json() {
+        let tools = Entity::Tool(ToolEntity {
+            project_name: "web".to_string(),
+        });
+        let id = tools.id();
+        assert_eq!(id, EntityId::new("tool-web", "Tools (web)", Role::Tool));
+        assert_eq!(id.to_string(), "Tools (web) (tool-web)");
+
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(
+            json,
+            r#"{"id":"tool-web","name":"Tools (web)","role":"Tool"}"#
+        );
+        assert_eq!(serde_json::from_str::<EntityId>(&json).unwrap(), id);
+
+        let json = serde_json::to_string(&tools).unwrap();
+        assert_eq!(json, r#"{"type":"Tool","data":{"project_name":"web"}}"#);
+        assert_eq!(serde_json::from_str::<Entity>(&json).unwrap().id(), id);
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, Instrument, Span};
+
+/// Coalesces bursts of messages per key (e.g. per chat). Every push restarts
+/// the key's timer; once `window` passes without a new push, the buffered
+/// parts are joined with newlines and handed to the latest push's `flush`.
+pub struct Debouncer<K> {
+    window: Duration,
+    pending: Arc<Mutex<HashMap<K, Pending>>>,
+}
+
+struct Pending {
+    parts: Vec<String>,
+    // Bumped on every push; a timer only flushes if it is still the latest
+    generation: u64,
+}
+
+impl<K> Debouncer<K>
+where
+    K: Eq + Hash + Clone + Send + std::fmt::Debug + 'static,
+{
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Buffer `text` under `key`, superseding any flush already scheduled for it.
+    pub fn push<F, Fut>(&self, key: K, text: String, flush: F)
+    where
+        F: FnOnce(String) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let generation = {
+            let mut pending = self.pending.lock().unwrap();
+            let entry = pending.entry(key.clone()).or_insert(Pending {
+                parts: Vec::new(),
+                generation: 0,
+            });
+            entry.parts.push(text);
+            entry.generation += 1;
+            entry.generation
+        };
+
+        let pending = self.pending.clone();
+        let window = self.window;
+        let flush_later = async move {
+            tokio::time::sleep(window).await;
+
+            let parts = {
+                let mut pending = pending.lock().unwrap();
+                match pending.get(&key) {
+                    Some(entry) if entry.generation == generation => {
+                        pending.remove(&key).map(|entry| entry.parts)
+                    }
+                    // A newer message restarted the window
+                    _ => None,
+                }
+            };
+
+            if let Some(parts) = parts {
+                debug!("Flushing {} buffered messages for {:?}", parts.len(), key);
+                flush(parts.join("\n")).await;
+            }
+        };
+        // Keep the caller's span (e.g. the chat's) on the delayed flush
+        tokio::spawn(flush_later.instrument(Span::current()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+    use tokio::time::{sleep, Instant};
+
+    const WINDOW: Duration = Duration::from_millis(500);
+
+    type Flushed = (&'static str, String, Instant);
+
+    /// Push `text` under `key`; its flush reports the joined text and when it ran.
+    fn push(
+        debouncer: &Debouncer<&'static str>,
+        flushed: &mpsc::UnboundedSender<Flushed>,
+        key: &'static str,
+        text: &str,
+    ) {
+        let flushed = flushed.clone();
+        debouncer.push(key, text.to_string(), move |joined| async move {
+            flushed.send((key, joined, Instant::now())).unwrap();
+        });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_burst_is_flushed_once_as_one_message() {
+        let debouncer = Debouncer::new(WINDOW);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        push(&debouncer, &tx, "chat", "one");
+        sleep(Duration::from_millis(100)).await;
+        push(&debouncer, &tx, "chat", "two");
+        sleep(Duration::from_millis(100)).await;
+        push(&debouncer, &tx, "chat", "three");
+
+        let (key, text, _) = rx.recv().await.unwrap();
+        assert_eq!((key, text.as_str()), ("chat", "one\ntwo\nthree"));
+        sleep(WINDOW * 2).await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_flush_comes_a_window_after_the_last_push() {
+        let debouncer = Debouncer::new(WINDOW);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let start = Instant::now();
+
+        push(&debouncer, &tx, "chat", "one");
+        // Each push restarts the window, however close to its end
+        sleep(WINDOW - Duration::from_millis(1)).await;
+        push(&debouncer, &tx, "chat", "two");
+
+        let (_, text, at) = rx.recv().await.unwrap();
+        assert_eq!(text, "one\ntwo");
+        assert_eq!(at - start, WINDOW * 2 - Duration::from_millis(1));
+
+        // A later message starts a new burst
+        push(&debouncer, &tx, "chat", "three");
+        let (_, text, later) = rx.recv().await.unwrap();
+        assert_eq!(text, "three");
+        assert_eq!(later - at, WINDOW);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keys_are_debounced_separately() {
+        let debouncer = Debouncer::new(WINDOW);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let start = Instant::now();
+
+        push(&debouncer, &tx, "alice", "a1");
+        sleep(Duration::from_millis(100)).await;
+        push(&debouncer, &tx, "bob", "b1");
+        sleep(Duration::from_millis(100)).await;
+        push(&debouncer, &tx, "alice", "a2");
+
+        // Bob's message neither joins Alice's burst nor holds it up
+        let (key, text, at) = rx.recv().await.unwrap();
+        assert_eq!((key, text.as_str()), ("bob", "b1"));
+        assert_eq!(at - start, Duration::from_millis(100) + WINDOW);
+        let (key, text, at) = rx.recv().await.unwrap();
+        assert_eq!((key, text.as_str()), ("alice", "a1\na2"));
+        assert_eq!(at - start, Duration::from_millis(200) + WINDOW);
+    }
+}
@@ -0,0 +1,193 @@
+//! Rate-limited message edits for replies shown while they are still growing.
+//!
+//! Telegram allows roughly one edit per second per chat and answers anything
+//! faster with 429s; `/exec` edits its output message through this.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, Instrument, Span};
+
+/// Coalesces edits of a growing message per key (e.g. per chat). Each push
+/// replaces the text waiting to be shown; at most one edit per `min_interval`
+/// goes out, always with the latest text, and [`EditThrottle::finish`] makes
+/// sure the complete text is the last edit.
+pub struct EditThrottle<K> {
+    min_interval: Duration,
+    chats: Arc<Mutex<HashMap<K, ChatEdits>>>,
+}
+
+#[derive(Default)]
+struct ChatEdits {
+    // Newest text not yet shown
+    latest: Option<String>,
+    last_edit: Option<Instant>,
+    // Whether a delayed edit is already on its way
+    scheduled: bool,
+    // Held for the duration of each edit so they land in order
+    edit_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl ChatEdits {
+    /// How long until the next edit is allowed.
+    fn wait(&self, min_interval: Duration) -> Duration {
+        self.last_edit
+            .map(|at| min_interval.saturating_sub(at.elapsed()))
+            .unwrap_or_default()
+    }
+}
+
+impl<K> EditThrottle<K>
+where
+    K: Eq + Hash + Clone + Send + std::fmt::Debug + 'static,
+{
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            chats: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Show `text` (the whole message so far) under `key` as soon as the rate
+    /// limit allows. Text pushed before then replaces it.
+    pub fn push<F, Fut>(&self, key: K, text: String, edit: F)
+    where
+        F: FnOnce(String) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (delay, edit_lock) = {
+            let mut chats = self.chats.lock().unwrap();
+            let chat = chats.entry(key.clone()).or_default();
+            chat.latest = Some(text);
+            if chat.scheduled {
+                return;
+            }
+            chat.scheduled = true;
+            (chat.wait(self.min_interval), chat.edit_lock.clone())
+        };
+
+        let chats = self.chats.clone();
+        let edit_later = async move {
+            tokio::time::sleep(delay).await;
+
+            let _editing = edit_lock.lock().await;
+            let text = {
+                let mut chats = chats.lock().unwrap();
+                // Gone once finished; the final edit already covers this text
+                let Some(chat) = chats.get_mut(&key) else {
+                    return;
+                };
+                chat.scheduled = false;
+                let text = chat.latest.take();
+                if text.is_some() {
+                    chat.last_edit = Some(Instant::now());
+                }
+                text
+            };
+
+            if let Some(text) = text {
+                debug!("Editing streamed message for {:?}", key);
+                edit(text).await;
+            }
+        };
+        // Keep the caller's span (e.g. the chat's) on the delayed edit
+        tokio::spawn(edit_later.instrument(Span::current()));
+    }
+
+    /// Show the complete `text` under `key`, after any edit in flight and no
+    /// sooner than the rate limit allows, and forget the key.
+    pub async fn finish<F, Fut>(&self, key: K, text: String, edit: F)
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let edit_lock = match self.chats.lock().unwrap().get(&key) {
+            Some(chat) => chat.edit_lock.clone(),
+            None => Arc::default(),
+        };
+
+        let _editing = edit_lock.lock().await;
+        // Dropping the entry turns any pending interim edit into a no-op
+        let delay = match self.chats.lock().unwrap().remove(&key) {
+            Some(chat) => chat.wait(self.min_interval),
+            None => Duration::ZERO,
+        };
+        tokio::time::sleep(delay).await;
+        edit(text).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Edits = Arc<Mutex<Vec<(Instant, String)>>>;
+
+    fn recorder(edits: &Edits) -> impl FnOnce(String) -> std::future::Ready<()> {
+        let edits = edits.clone();
+        move |text| {
+            edits.lock().unwrap().push((Instant::now(), text));
+            std::future::ready(())
+        }
+    }
+
+    #[tokio::test]
+    async fn rapid_chunks_are_edited_no_faster_than_the_limit() {
+        let min_interval = Duration::from_millis(100);
+        let throttle = EditThrottle::new(min_interval);
+        let edits = Edits::default();
+
+        let started = Instant::now();
+        let mut text = String::new();
+        for i in 0..100 {
+            text.push_str(&format!("chunk {} ", i));
+            throttle.push(42, text.clone(), recorder(&edits));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        // Like `/exec`, the complete message has more than the last chunk
+        text.push_str("done");
+        throttle.finish(42, text.clone(), recorder(&edits)).await;
+        let elapsed = started.elapsed();
+
+        let edits = edits.lock().unwrap();
+        assert!(edits.len() >= 3, "only {} edits", edits.len());
+        let allowed = (elapsed.as_millis() / min_interval.as_millis()) as usize + 1;
+        assert!(
+            edits.len() <= allowed,
+            "{} edits in {:?}",
+            edits.len(),
+            elapsed
+        );
+        for pair in edits.windows(2) {
+            let gap = pair[1].0 - pair[0].0;
+            // Timestamps are taken just after the throttle's own, and a busy
+            // test machine can delay one, hence the slack
+            assert!(
+                gap + Duration::from_millis(20) >= min_interval,
+                "edits {:?} apart",
+                gap
+            );
+            assert!(
+                pair[1].1.len() > pair[0].1.len(),
+                "edits arrived out of order"
+            );
+        }
+        assert_eq!(edits.last().unwrap().1, text);
+    }
+
+    #[tokio::test]
+    async fn chats_are_limited_separately() {
+        let throttle = EditThrottle::new(Duration::from_secs(60));
+        let edits = Edits::default();
+
+        throttle.push(1, "one".to_string(), recorder(&edits));
+        throttle.push(2, "two".to_string(), recorder(&edits));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut texts: Vec<String> = edits.lock().unwrap().iter().map(|e| e.1.clone()).collect();
+        texts.sort();
+        assert_eq!(texts, ["one", "two"]);
+    }
+}
@@ -1 +1,36 @@
+pub mod debounce;
+pub mod edit_throttle;
+pub mod outbound;
+pub mod slack;
 pub mod telegram;
+
+/// Whether a user known by any of `identities` may use an interface. A
+/// configured whitelist always applies; an empty one denies everyone unless
+/// `allow_all` explicitly opens the interface.
+pub fn is_whitelisted(whitelist: &[String], allow_all: bool, identities: &[&str]) -> bool {
+    if whitelist.is_empty() {
+        return allow_all;
+    }
+    identities
+        .iter()
+        .any(|id| whitelist.iter().any(|allowed| allowed == id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_whitelist_denies_unless_allow_all() {
+        assert!(!is_whitelisted(&[], false, &["U1"]));
+        assert!(is_whitelisted(&[], true, &["U1"]));
+    }
+
+    #[test]
+    fn whitelist_applies_even_with_allow_all() {
+        let whitelist = vec!["U1".to_string()];
+        assert!(is_whitelisted(&whitelist, true, &["U1"]));
+        assert!(!is_whitelisted(&whitelist, true, &["U2"]));
+        assert!(!is_whitelisted(&whitelist, false, &[]));
+    }
+}
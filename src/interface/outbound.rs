@@ -0,0 +1,262 @@
+use crate::{
+    bus::{Event, EventBus},
+    chat::{ChatMessage, REPLY_TO_KEY},
+    entity::Role,
+};
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, info, warn};
+
+/// Metadata key holding where an agent reply should go, as `<prefix>:<address>`
+/// (e.g. `tg:12345`, `slack:C0123`). Interfaces set it on inbound messages and
+/// the bridge carries it over to the reply.
+pub const RECIPIENT_KEY: &str = "recipient";
+
+/// Format a recipient value for an interface's prefix.
+pub fn recipient(prefix: &str, address: impl std::fmt::Display) -> String {
+    format!("{}:{}", prefix, address)
+}
+
+/// Something that can deliver agent replies to one kind of channel.
+pub trait MessageSink: Send + Sync {
+    /// Deliver `msg` to `address`, the recipient with the sink's prefix stripped.
+    fn deliver<'a>(&'a self, address: &'a str, msg: &'a ChatMessage) -> BoxFuture<'a, Result<()>>;
+}
+
+/// How long a prompt's recipient is remembered for replies that lost theirs.
+const FALLBACK_RECIPIENT_TTL: Duration = Duration::from_secs(60 * 60);
+/// Most prompts whose recipient is remembered at once.
+const MAX_FALLBACK_RECIPIENTS: usize = 1024;
+
+/// Recipients of recent prompts by message id. `order` lists the ids as they
+/// were remembered, so the oldest entry, and any that expired, are at its front.
+#[derive(Default)]
+struct PromptRecipients {
+    by_prompt: HashMap<String, (Instant, String)>,
+    order: VecDeque<(Instant, String)>,
+}
+
+impl PromptRecipients {
+    /// Remember `recipient` for `prompt_id`, first dropping expired entries and,
+    /// when full, the oldest one.
+    fn insert(&mut self, prompt_id: &str, recipient: &str, now: Instant) {
+        while let Some((at, _)) = self.order.front() {
+            let expired = now.saturating_duration_since(*at) >= FALLBACK_RECIPIENT_TTL;
+            if !expired && self.order.len() < MAX_FALLBACK_RECIPIENTS {
+                break;
+            }
+            let Some((at, id)) = self.order.pop_front() else {
+                break;
+            };
+            // The prompt may have been remembered again since; that entry stays
+            if self
+                .by_prompt
+                .get(&id)
+                .is_some_and(|(newer, _)| *newer == at)
+            {
+                self.by_prompt.remove(&id);
+            }
+        }
+        self.by_prompt
+            .insert(prompt_id.to_string(), (now, recipient.to_string()));
+        self.order.push_back((now, prompt_id.to_string()));
+    }
+
+    fn get(&self, prompt_id: &str) -> Option<&str> {
+        self.by_prompt
+            .get(prompt_id)
+            .filter(|(at, _)| at.elapsed() < FALLBACK_RECIPIENT_TTL)
+            .map(|(_, recipient)| recipient.as_str())
+    }
+}
+
+/// Routes agent replies from the bus to the sink registered for their recipient's prefix.
+pub struct OutboundRouter {
+    bus: Arc<EventBus>,
+    sinks: RwLock<HashMap<String, Arc<dyn MessageSink>>>,
+    // Recipient of each recent user prompt, for replies missing `recipient`
+    prompt_recipients: Mutex<PromptRecipients>,
+}
+
+impl OutboundRouter {
+    pub fn new(bus: Arc<EventBus>) -> Self {
+        Self {
+            bus,
+            sinks: RwLock::new(HashMap::new()),
+            prompt_recipients: Mutex::new(PromptRecipients::default()),
+        }
+    }
+
+    /// Remember who sent prompt `prompt_id`, dropping stale and excess entries.
+    fn remember_recipient(&self, prompt_id: &str, recipient: &str) {
+        self.prompt_recipients
+            .lock()
+            .unwrap()
+            .insert(prompt_id, recipient, Instant::now());
+    }
+
+    /// Recipient for a reply without one: whoever sent the prompt it answers.
+    fn fallback_recipient(&self, msg: &ChatMessage) -> Option<String> {
+        let prompt_id = msg.metadata.get(REPLY_TO_KEY)?;
+        self.prompt_recipients
+            .lock()
+            .unwrap()
+            .get(prompt_id)
+            .map(str::to_string)
+    }
+
+    /// Claim recipients starting with `<prefix>:`. Registering a prefix again replaces its sink.
+    pub fn register(&self, prefix: &str, sink: Arc<dyn MessageSink>) {
+        info!("Registered outbound sink for '{}:'", prefix);
+        self.sinks.write().unwrap().insert(prefix.to_string(), sink);
+    }
+
+    pub async fn run(&self) {
+        let mut rx = self.bus.subscribe();
+
+        loop {
+            let msg = match rx.recv().await {
+                Ok(Event::ChatMessage(msg))
+                    if matches!(msg.sender.role, Role::Agent | Role::Tool) =>
+                {
+                    msg
+                }
+                Ok(Event::ChatMessage(msg)) if msg.sender.role == Role::User => {
+                    if let Some(recipient) = msg.metadata.get(RECIPIENT_KEY) {
+                        self.remember_recipient(&msg.id, recipient);
+                    }
+                    continue;
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Outbound router lagged, skipped {} events", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let recipient = match msg.metadata.get(RECIPIENT_KEY) {
+                Some(recipient) => recipient.clone(),
+                None => match self.fallback_recipient(&msg) {
+                    Some(recipient) => {
+                        warn!(
+                            "Agent reply {} has no recipient, routing to its prompt's sender {}",
+                            msg.id, recipient
+                        );
+                        recipient
+                    }
+                    None => {
+                        warn!("Agent reply has no recipient, not delivering: {}", msg.id);
+                        continue;
+                    }
+                },
+            };
+            let Some((prefix, address)) = recipient.split_once(':') else {
+                warn!(
+                    "Malformed recipient '{}', not delivering: {}",
+                    recipient, msg.id
+                );
+                continue;
+            };
+
+            let sink = self.sinks.read().unwrap().get(prefix).cloned();
+            match sink {
+                Some(sink) => {
+                    if let Err(e) = sink.deliver(address, &msg).await {
+                        error!("Failed to deliver reply to {}:{}: {}", prefix, address, e);
+                    }
+                }
+                None => warn!("No outbound sink registered for '{}:'", prefix),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::PROJECT_NAME_KEY;
+    use crate::entity::EntityId;
+
+    fn reply(metadata: &[(&str, &str)]) -> ChatMessage {
+        ChatMessage {
+            id: "reply".to_string(),
+            chat_id: None,
+            sender: EntityId::new("agent-shared", "shared", Role::Agent),
+            content: "done".to_string(),
+            timestamp: chrono::Utc::now(),
+            metadata: metadata
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn reply_falls_back_to_its_own_prompts_sender() {
+        let router = OutboundRouter::new(Arc::new(EventBus::new()));
+        router.remember_recipient("prompt-a", "tg:1");
+        router.remember_recipient("prompt-b", "tg:2");
+
+        let msg = reply(&[(REPLY_TO_KEY, "prompt-a"), (PROJECT_NAME_KEY, "shared")]);
+        assert_eq!(router.fallback_recipient(&msg).as_deref(), Some("tg:1"));
+    }
+
+    #[test]
+    fn reply_without_prompt_is_not_sent_to_the_projects_last_prompter() {
+        let router = OutboundRouter::new(Arc::new(EventBus::new()));
+        router.remember_recipient("prompt-a", "tg:1");
+
+        let msg = reply(&[(PROJECT_NAME_KEY, "shared")]);
+        assert_eq!(router.fallback_recipient(&msg), None);
+        let msg = reply(&[(REPLY_TO_KEY, "unknown"), (PROJECT_NAME_KEY, "shared")]);
+        assert_eq!(router.fallback_recipient(&msg), None);
+    }
+
+    #[test]
+    fn remembered_prompts_are_bounded() {
+        let router = OutboundRouter::new(Arc::new(EventBus::new()));
+        for i in 0..=MAX_FALLBACK_RECIPIENTS {
+            router.remember_recipient(&format!("prompt-{}", i), "tg:1");
+        }
+        let recipients = router.prompt_recipients.lock().unwrap();
+        assert_eq!(recipients.by_prompt.len(), MAX_FALLBACK_RECIPIENTS);
+        assert_eq!(recipients.order.len(), MAX_FALLBACK_RECIPIENTS);
+        assert_eq!(recipients.get("prompt-0"), None);
+        assert_eq!(recipients.get("prompt-1"), Some("tg:1"));
+    }
+
+    #[test]
+    fn expired_prompts_are_dropped_as_new_ones_arrive() {
+        let mut recipients = PromptRecipients::default();
+        let now = Instant::now();
+        let long_ago = now - FALLBACK_RECIPIENT_TTL - Duration::from_secs(1);
+        recipients.insert("old", "tg:1", long_ago);
+        assert_eq!(recipients.get("old"), None);
+
+        recipients.insert("new", "tg:2", now);
+        assert!(!recipients.by_prompt.contains_key("old"));
+        assert_eq!(recipients.order.len(), 1);
+        assert_eq!(recipients.get("new"), Some("tg:2"));
+    }
+
+    #[test]
+    fn a_prompt_remembered_again_outlives_its_first_entry() {
+        let mut recipients = PromptRecipients::default();
+        let now = Instant::now();
+        recipients.insert("again", "tg:1", now);
+        recipients.insert("again", "tg:2", now + Duration::from_secs(1));
+        for i in 2..=MAX_FALLBACK_RECIPIENTS {
+            recipients.insert(&format!("prompt-{}", i), "tg:3", now);
+        }
+
+        // Evicting the first entry for "again" leaves its newer one
+        assert_eq!(recipients.order.len(), MAX_FALLBACK_RECIPIENTS);
+        assert_eq!(recipients.get("again"), Some("tg:2"));
+        assert_eq!(recipients.get("prompt-2"), Some("tg:3"));
+    }
+}
@@ -0,0 +1,688 @@
+use crate::{
+    agent::bridge::not_ready_message,
+    bus::{Event, EventBus},
+    chat::{ChatMessage, PROJECT_NAME_KEY},
+    entity::{EntityId, Role},
+    interface::is_whitelisted,
+    interface::outbound::{self, MessageSink, OutboundRouter, RECIPIENT_KEY},
+    manager::{launch_failure_message, Manager},
+};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::post,
+    Json, Router,
+};
+use futures::future::BoxFuture;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+const SLACK_API_URL: &str = "https://slack.com/api";
+
+/// Outbound recipient prefix for Slack channels (`slack:<channel>`)
+const RECIPIENT_PREFIX: &str = "slack";
+
+/// Metadata key with the thread a prompt was posted in, where its reply goes
+const SLACK_THREAD_TS_KEY: &str = "slack_thread_ts";
+
+/// Slack recommends rejecting signed requests older than five minutes to prevent replays.
+const MAX_REQUEST_AGE_SECS: i64 = 60 * 5;
+
+/// Active project for a Slack channel. Threads inherit their channel's project;
+/// replies are posted back into the thread the prompt came from.
+#[derive(Debug, Clone)]
+struct ChatSession {
+    active_project: String,
+}
+
+#[derive(Clone)]
+pub struct SlackInterface {
+    bus: Arc<EventBus>,
+    manager: Arc<Manager>,
+    bot_token: String,
+    signing_secret: String,
+    http: reqwest::Client,
+    chat_sessions: Arc<Mutex<HashMap<String, ChatSession>>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum EventsPayload {
+    UrlVerification {
+        challenge: String,
+    },
+    EventCallback {
+        event: SlackEvent,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct SlackEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    user: Option<String>,
+    text: Option<String>,
+    channel: Option<String>,
+    channel_type: Option<String>,
+    ts: Option<String>,
+    thread_ts: Option<String>,
+    bot_id: Option<String>,
+    subtype: Option<String>,
+}
+
+/// Form body Slack POSTs for slash commands.
+#[derive(Debug, Deserialize)]
+struct SlashCommand {
+    text: String,
+    channel_id: String,
+    user_id: String,
+}
+
+impl SlackInterface {
+    pub fn new(
+        bus: Arc<EventBus>,
+        manager: Arc<Manager>,
+        outbound: &OutboundRouter,
+        bot_token: String,
+        signing_secret: String,
+    ) -> Self {
+        let interface = Self {
+            bus,
+            manager,
+            bot_token,
+            signing_secret,
+            http: reqwest::Client::new(),
+            chat_sessions: Arc::new(Mutex::new(HashMap::new())),
+        };
+        outbound.register(RECIPIENT_PREFIX, Arc::new(interface.clone()));
+        interface
+    }
+
+    /// Webhook routes to mount on the main axum app.
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/slack/events", post(events_handler))
+            .route("/slack/commands", post(commands_handler))
+            .with_state(self.clone())
+    }
+
+    /// Post operator announcements to every channel with an active project.
+    /// Agent replies are delivered by the outbound router.
+    pub async fn run(&self) -> anyhow::Result<()> {
+        info!("Starting Slack announcement listener...");
+        let mut bus_rx = self.bus.subscribe();
+
+        loop {
+            let event = match bus_rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(
+                        "Slack announcement listener lagged, skipped {} events",
+                        skipped
+                    );
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if let Event::Announcement { message } = event {
+                // Only channels that have entered a project are known to us
+                let channels: Vec<String> =
+                    self.chat_sessions.lock().unwrap().keys().cloned().collect();
+                for channel in channels {
+                    if let Err(e) = self
+                        .post_message(&channel, &format!(":mega: {}", message), None)
+                        .await
+                    {
+                        error!("Failed to send announcement to Slack: {}", e);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_active_project(&self, channel: &str) -> Option<ChatSession> {
+        let sessions = self.chat_sessions.lock().unwrap();
+        sessions.get(channel).cloned()
+    }
+
+    fn set_active_project(&self, channel: String, project_name: String) {
+        let mut sessions = self.chat_sessions.lock().unwrap();
+        sessions.insert(
+            channel,
+            ChatSession {
+                active_project: project_name,
+            },
+        );
+    }
+
+    /// Whether Slack user `user` (an id like `U0123ABC`) may use the app at all.
+    fn is_authorized(&self, user: &str) -> bool {
+        let slack = &self.manager.config().slack;
+        is_whitelisted(&slack.whitelist, slack.allow_all, &[user])
+    }
+
+    /// Whether `user` may see and enter `project`, per `project_access`.
+    fn can_access_project(&self, user: &str, project: &str) -> bool {
+        self.manager.config().can_access_project(user, project)
+    }
+
+    /// Check Slack's `v0` HMAC-SHA256 request signature over `v0:{timestamp}:{body}`.
+    fn verify_signature(&self, headers: &HeaderMap, body: &[u8]) -> bool {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+        let (Some(timestamp), Some(signature)) = (
+            header("x-slack-request-timestamp"),
+            header("x-slack-signature"),
+        ) else {
+            return false;
+        };
+
+        let Ok(sent_at) = timestamp.parse::<i64>() else {
+            return false;
+        };
+        if (chrono::Utc::now().timestamp() - sent_at).abs() > MAX_REQUEST_AGE_SECS {
+            warn!("Rejecting stale Slack request (timestamp {})", sent_at);
+            return false;
+        }
+
+        let Some(expected) = signature
+            .strip_prefix("v0=")
+            .and_then(|sig| hex::decode(sig).ok())
+        else {
+            return false;
+        };
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.signing_secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(b"v0:");
+        mac.update(timestamp.as_bytes());
+        mac.update(b":");
+        mac.update(body);
+
+        // Constant-time comparison
+        mac.verify_slice(&expected).is_ok()
+    }
+
+    async fn post_message(
+        &self,
+        channel: &str,
+        text: &str,
+        thread_ts: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let mut body = serde_json::json!({ "channel": channel, "text": text });
+        if let Some(ts) = thread_ts {
+            body["thread_ts"] = serde_json::Value::String(ts.to_string());
+        }
+
+        let resp: serde_json::Value = self
+            .http
+            .post(format!("{}/chat.postMessage", SLACK_API_URL))
+            .bearer_auth(&self.bot_token)
+            .json(&body)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        // The Web API reports failures in the body with a 200 status
+        if resp.get("ok").and_then(|v| v.as_bool()) != Some(true) {
+            anyhow::bail!(
+                "chat.postMessage failed: {}",
+                resp.get("error")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown error")
+            );
+        }
+
+        Ok(())
+    }
+
+    fn handle_event(&self, event: SlackEvent) {
+        // Ignore our own (and other bots') messages and edits/joins/etc.
+        if event.bot_id.is_some() || event.subtype.is_some() {
+            return;
+        }
+
+        // Mentions arrive as `app_mention`; plain `message` events are only handled in DMs
+        // so a mention in a channel isn't processed twice.
+        let relevant = event.kind == "app_mention"
+            || (event.kind == "message" && event.channel_type.as_deref() == Some("im"));
+        if !relevant {
+            return;
+        }
+
+        let (Some(user), Some(text), Some(channel), Some(ts)) =
+            (event.user, event.text, event.channel, event.ts)
+        else {
+            return;
+        };
+
+        let text = strip_mentions(&text);
+        if text.is_empty() {
+            return;
+        }
+
+        let thread_ts = event.thread_ts.unwrap_or(ts);
+
+        // Access to the channel's project is checked on every message: it may
+        // have been entered by someone else
+        let denial = match self.get_active_project(&channel) {
+            _ if !self.is_authorized(&user) => {
+                warn!("Unauthorized Slack user {}", user);
+                Some("You are not authorized to use this app.".to_string())
+            }
+            Some(session) if !self.can_access_project(&user, &session.active_project) => {
+                warn!(
+                    "Slack user {} denied access to {}",
+                    user, session.active_project
+                );
+                Some(format!(
+                    "You don't have access to {}.",
+                    session.active_project
+                ))
+            }
+            _ => None,
+        };
+        if let Some(denial) = denial {
+            let interface = self.clone();
+            tokio::spawn(async move {
+                let _ = interface
+                    .post_message(&channel, &denial, Some(&thread_ts))
+                    .await;
+            });
+            return;
+        }
+
+        let Some(session) = self.get_active_project(&channel) else {
+            let interface = self.clone();
+            tokio::spawn(async move {
+                let _ = interface
+                    .post_message(
+                        &channel,
+                        "No active project in this channel. Use `/thalassa enter <project-name>` first.",
+                        Some(&thread_ts),
+                    )
+                    .await;
+            });
+            return;
+        };
+
+        let mut metadata = HashMap::new();
+        metadata.insert(
+            RECIPIENT_KEY.to_string(),
+            outbound::recipient(RECIPIENT_PREFIX, &channel),
+        );
+        metadata.insert(SLACK_THREAD_TS_KEY.to_string(), thread_ts.clone());
+        metadata.insert(PROJECT_NAME_KEY.to_string(), session.active_project.clone());
+
+        let chat_msg = ChatMessage {
+            id: Uuid::new_v4().to_string(),
+            chat_id: Some(outbound::recipient(RECIPIENT_PREFIX, &channel)),
+            sender: EntityId::new(user, "SlackUser", Role::User),
+            content: text,
+            timestamp: chrono::Utc::now(),
+            metadata,
+        };
+
+        // Fail fast if the agent can't take prompts instead of leaving the user waiting
+        let interface = self.clone();
+        tokio::spawn(async move {
+            if interface.manager.agent_ready(&session.active_project).await {
+                interface.bus.publish(Event::ChatMessage(chat_msg));
+            } else {
+                let _ = interface
+                    .post_message(
+                        &channel,
+                        &not_ready_message(&session.active_project),
+                        Some(&thread_ts),
+                    )
+                    .await;
+            }
+        });
+    }
+
+    async fn handle_command(&self, cmd: SlashCommand) -> String {
+        let mut parts = cmd.text.trim().splitn(2, char::is_whitespace);
+        let sub = parts.next().unwrap_or_default();
+        let arg = parts.next().unwrap_or_default().trim().to_string();
+
+        if !self.is_authorized(&cmd.user_id) {
+            warn!("Unauthorized Slack user {}", cmd.user_id);
+            return "You are not authorized to use this app.".to_string();
+        }
+        let accessible = |projects: Vec<String>| -> Vec<String> {
+            projects
+                .into_iter()
+                .filter(|p| self.can_access_project(&cmd.user_id, p))
+                .collect()
+        };
+
+        match sub {
+            "projects" => match self.manager.list_projects().await.map(accessible) {
+                Ok(projects) if projects.is_empty() => "No projects found.".to_string(),
+                Ok(projects) => {
+                    let active = self.get_active_project(&cmd.channel_id);
+                    let list: Vec<String> = projects
+                        .iter()
+                        .map(|p| match &active {
+                            Some(s) if &s.active_project == p => format!("→ {}", p),
+                            _ => format!("  {}", p),
+                        })
+                        .collect();
+                    format!("Projects:\n{}", list.join("\n"))
+                }
+                Err(e) => {
+                    error!("Failed to list projects: {}", e);
+                    "Failed to retrieve project list.".to_string()
+                }
+            },
+            "enter" if !arg.is_empty() => {
+                // Projects the user may not access are reported as missing
+                match self.manager.list_projects().await.map(accessible) {
+                    Ok(projects) if !projects.contains(&arg) => {
+                        return format!("Project '{}' not found.", arg);
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("Failed to list projects: {}", e);
+                        return "Failed to retrieve project list.".to_string();
+                    }
+                }
+
+                // Slack expects an answer within 3s, so launch in the background
+                // and report the outcome in the channel.
+                let interface = self.clone();
+                let channel = cmd.channel_id.clone();
+                let project_name = arg.clone();
+                tokio::spawn(async move {
+                    let text = match interface
+                        .manager
+                        .launch_project(project_name.clone())
+                        .await
+                    {
+                        Ok(_) => {
+                            interface.set_active_project(channel.clone(), project_name.clone());
+                            format!(
+                                "✓ Entered [{}]\n\nMention me to chat with this project.",
+                                project_name
+                            )
+                        }
+                        Err(e) => {
+                            error!("Failed to launch project: {}", e);
+                            launch_failure_message(&project_name, &e)
+                        }
+                    };
+                    if let Err(e) = interface.post_message(&channel, &text, None).await {
+                        error!("Failed to send launch result to Slack: {}", e);
+                    }
+                });
+
+                format!("Launching {}...", arg)
+            }
+            _ => "Usage:\n`/thalassa projects` - list available projects\n`/thalassa enter <project-name>` - enter a project in this channel".to_string(),
+        }
+    }
+}
+
+/// Replies go back into the thread the prompt came from.
+impl MessageSink for SlackInterface {
+    fn deliver<'a>(
+        &'a self,
+        address: &'a str,
+        msg: &'a ChatMessage,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let thread_ts = msg.metadata.get(SLACK_THREAD_TS_KEY).map(|s| s.as_str());
+
+            // Tool activity is shown muted, in italics
+            if msg.sender.role == Role::Tool {
+                let text = tool_activity_text(&msg.content);
+                return self.post_message(address, &text, thread_ts).await;
+            }
+
+            // Files stay in the container; point at them rather than uploading
+            let mut text = msg.content.clone();
+            for attachment in msg.attachments() {
+                text.push_str(&format!("\n:paperclip: `{}`", attachment.path));
+            }
+
+            self.post_message(address, &text, thread_ts).await
+        })
+    }
+}
+
+/// Remove `<@U123>` user mentions (including the bot's own) from message text.
+fn strip_mentions(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("<@") {
+        out.push_str(&rest[..start]);
+        match rest[start..].find('>') {
+            Some(end) => rest = &rest[start + end + 1..],
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+    out.push_str(rest);
+    out.trim().to_string()
+}
+
+async fn events_handler(
+    State(interface): State<SlackInterface>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
+    if !interface.verify_signature(&headers, &body) {
+        return (StatusCode::UNAUTHORIZED, Json(serde_json::Value::Null));
+    }
+
+    // Slack redelivers events it thinks timed out; we already handled the original
+    if headers.contains_key("x-slack-retry-num") {
+        return (StatusCode::OK, Json(serde_json::Value::Null));
+    }
+
+    let payload: EventsPayload = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!("Failed to parse Slack event: {}", e);
+            return (StatusCode::BAD_REQUEST, Json(serde_json::Value::Null));
+        }
+    };
+
+    match payload {
+        EventsPayload::UrlVerification { challenge } => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "challenge": challenge })),
+        ),
+        EventsPayload::EventCallback { event } => {
+            interface.handle_event(event);
+            (StatusCode::OK, Json(serde_json::Value::Null))
+        }
+        EventsPayload::Other => (StatusCode::OK, Json(serde_json::Value::Null)),
+    }
+}
+
+async fn commands_handler(
+    State(interface): State<SlackInterface>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
+    if !interface.verify_signature(&headers, &body) {
+        return (StatusCode::UNAUTHORIZED, String::new());
+    }
+
+    let cmd: SlashCommand = match serde_urlencoded::from_bytes(&body) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            error!("Failed to parse Slack command: {}", e);
+            return (StatusCode::BAD_REQUEST, String::new());
+        }
+    };
+
+    // A plain-text body is shown to the invoking user only
+    (StatusCode::OK, interface.handle_command(cmd).await)
+}
+
+/// A tool activity message as muted (italic) Slack markup.
+fn tool_activity_text(content: &str) -> String {
+    format!("_{}_", content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slash_commands_carry_the_invoking_user() {
+        let body = "command=%2Fthalassa&text=enter+web&channel_id=C1&user_id=U1&user_name=alice";
+        let cmd: SlashCommand = serde_urlencoded::from_str(body).unwrap();
+        assert_eq!(cmd.user_id, "U1");
+        assert_eq!(cmd.channel_id, "C1");
+        assert_eq!(cmd.text, "enter web");
+    }
+
+    #[test]
+    fn mentions_are_stripped() {
+        assert_eq!(strip_mentions("<@U123> hello <@U456>there"), "hello there");
+    }
+
+    #[test]
+    fn tool_activity_is_italic() {
+        assert_eq!(tool_activity_text("🔧 cargo test"), "_🔧 cargo test_");
+    }
+
+    const SECRET: &str = "8f742231b10e8888abcd99yyyzzz85a5";
+    const BODY: &[u8] = b"token=xyz&command=%2Fthalassa&text=list";
+
+    async fn interface() -> SlackInterface {
+        let bus = Arc::new(EventBus::new());
+        let manager =
+            crate::manager::testing::manager(&["web"], crate::config::Config::default()).await;
+        SlackInterface::new(
+            bus.clone(),
+            manager,
+            &OutboundRouter::new(bus),
+            "xoxb-test".to_string(),
+            SECRET.to_string(),
+        )
+    }
+
+    /// Headers as Slack sends them for `body` at `timestamp`, signed with `secret`.
+    fn signed(secret: &str, timestamp: i64, body: &[u8]) -> HeaderMap {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("v0:{}:", timestamp).as_bytes());
+        mac.update(body);
+        let signature = format!("v0={}", hex::encode(mac.finalize().into_bytes()));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-slack-request-timestamp",
+            timestamp.to_string().parse().unwrap(),
+        );
+        headers.insert("x-slack-signature", signature.parse().unwrap());
+        headers
+    }
+
+    fn now() -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+
+    #[tokio::test]
+    async fn correctly_signed_requests_are_accepted() {
+        let interface = interface().await;
+        assert!(interface.verify_signature(&signed(SECRET, now(), BODY), BODY));
+    }
+
+    #[tokio::test]
+    async fn tampered_bodies_and_signatures_are_rejected() {
+        let interface = interface().await;
+        let sent_at = now();
+        let headers = signed(SECRET, sent_at, BODY);
+        assert!(
+            !interface.verify_signature(&headers, b"token=xyz&command=%2Fthalassa&text=stopall")
+        );
+
+        let mut tampered = headers.clone();
+        let signature = headers["x-slack-signature"].to_str().unwrap();
+        let last = if signature.ends_with('0') { "1" } else { "0" };
+        let flipped = format!("{}{}", &signature[..signature.len() - 1], last);
+        tampered.insert("x-slack-signature", flipped.parse().unwrap());
+        assert!(!interface.verify_signature(&tampered, BODY));
+
+        // Signed by someone who doesn't know the secret
+        assert!(!interface.verify_signature(&signed("guessed", now(), BODY), BODY));
+
+        // A valid signature moved to another timestamp
+        let mut moved = headers.clone();
+        moved.insert(
+            "x-slack-request-timestamp",
+            (sent_at - 1).to_string().parse().unwrap(),
+        );
+        assert!(!interface.verify_signature(&moved, BODY));
+    }
+
+    #[tokio::test]
+    async fn requests_outside_the_replay_window_are_rejected() {
+        let interface = interface().await;
+        let edge = MAX_REQUEST_AGE_SECS - 5;
+        assert!(interface.verify_signature(&signed(SECRET, now() - edge, BODY), BODY));
+
+        for timestamp in [
+            now() - MAX_REQUEST_AGE_SECS - 5,
+            now() + MAX_REQUEST_AGE_SECS + 5,
+        ] {
+            assert!(
+                !interface.verify_signature(&signed(SECRET, timestamp, BODY), BODY),
+                "{}",
+                timestamp
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_or_malformed_headers_are_rejected() {
+        let interface = interface().await;
+        let headers = signed(SECRET, now(), BODY);
+
+        for name in ["x-slack-request-timestamp", "x-slack-signature"] {
+            let mut missing = headers.clone();
+            missing.remove(name);
+            assert!(!interface.verify_signature(&missing, BODY), "{}", name);
+        }
+
+        let signature = headers["x-slack-signature"].to_str().unwrap().to_string();
+        let malformed = [
+            ("x-slack-request-timestamp", "yesterday".to_string()),
+            ("x-slack-signature", signature.replacen("v0=", "v1=", 1)),
+            (
+                "x-slack-signature",
+                signature.trim_start_matches("v0=").to_string(),
+            ),
+            ("x-slack-signature", "v0=not-hex".to_string()),
+        ];
+        for (name, value) in malformed {
+            let mut bad = headers.clone();
+            bad.insert(name, value.parse().unwrap());
+            assert!(
+                !interface.verify_signature(&bad, BODY),
+                "{}: {}",
+                name,
+                value
+            );
+        }
+    }
+}
@@ -1,14 +1,34 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 use tracing::{error, info};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 
 mod agent; // Added agent module
+mod backends;
 mod bus;
 mod chat;
+mod config;
 mod entity;
 mod interface;
 mod manager;
 mod mcp;
+mod recurrence;
+mod replay;
 mod store; // Added interface module
+mod tls;
+
+/// How long shutdown waits for buffered messages to reach the store.
+const PERSIST_FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Bounds of the delay before a dead scheduler is restarted.
+const SCHEDULER_RESTART_MIN: std::time::Duration = std::time::Duration::from_secs(1);
+const SCHEDULER_RESTART_MAX: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How long shutdown waits for "going offline" messages to be sent.
+const OFFLINE_NOTIFY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How long shutdown lets open HTTP requests finish before closing their connections.
+const SERVER_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -18,94 +38,436 @@ async fn main() -> anyhow::Result<()> {
         info!("No .env file found or failed to load: {}", e);
     }
 
-    // Initialize logging with default filter if RUST_LOG is not set
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
-        )
-        .init();
+    // In stdio mode stdout carries the MCP protocol, so logs must go to stderr
+    let mcp_stdio = std::env::args().skip(1).any(|arg| arg == "--mcp-stdio");
+
+    init_logging(mcp_stdio)?;
+
+    // Replaying a recording runs instead of the daemon, so nothing replayed
+    // reaches agents or chats
+    if let Some(path) = arg_value("--replay").map(PathBuf::from) {
+        let fast = std::env::args().skip(1).any(|arg| arg == "--replay-fast");
+        return replay::replay_detached(&path, fast).await;
+    }
 
     info!("Thalassa daemon starting...");
 
+    // Load configuration (file from --config or THALASSA_CONFIG, env vars override)
+    let config_path = config_path_from_args()
+        .or_else(|| std::env::var("THALASSA_CONFIG").ok().map(PathBuf::from));
+    if let Some(path) = &config_path {
+        info!("Loading config from {}", path.display());
+    }
+    let config = Arc::new(config::Config::load(config_path.as_deref())?);
+
     // Initialize the EventBus
     let bus = Arc::new(bus::EventBus::new());
 
+    // Opt-in recording of everything on the bus, for `--replay`
+    if let Some(path) = config.record_events.clone() {
+        let rx = bus.subscribe();
+        tokio::spawn(async move {
+            if let Err(e) = replay::record_events(rx, path).await {
+                error!("Event recorder stopped: {:#}", e);
+            }
+        });
+    }
+
     // Initialize the Store
-    // We use ~/.mothership/thalassa.db
-    let home_dir = std::env::var("HOME").unwrap_or_else(|_| ".".into());
-    let db_path = std::path::Path::new(&home_dir)
-        .join(".mothership")
-        .join("thalassa.db");
-
-    info!("Initializing store at {}", db_path.display());
-    let store = store::Store::new(&db_path).await?;
+    info!("Initializing store at {}", config.db_path.display());
+    let store = Arc::new(store::Store::new(&config.db_path).await?);
     store.init().await?;
 
+    // Agent replies reach interfaces through the outbound router
+    let outbound = Arc::new(interface::outbound::OutboundRouter::new(bus.clone()));
+
     // Initialize the Manager
-    let manager = Arc::new(manager::Manager::new(bus.clone())?);
+    let manager = Arc::new(manager::Manager::new(
+        bus.clone(),
+        config.clone(),
+        store.clone(),
+    )?);
 
-    // Spawn the scheduler in the background
-    let manager_clone = manager.clone();
-    let scheduler_handle = tokio::spawn(async move {
-        info!("Starting scheduler...");
-        manager_clone.start_scheduler().await;
-    });
+    // Launched as an MCP subprocess (e.g. by an editor): serve tools over stdio only.
+    // Chat interfaces and the scheduler stay with the long-running daemon.
+    if mcp_stdio {
+        let mcp_server = mcp::server::McpServer::new(manager.clone(), bus.clone())
+            .with_store(store.clone())
+            .with_max_body_bytes(config.max_body_bytes)
+            .with_request_limits(config.request_limits());
+        return mcp_server.serve_stdio().await;
+    }
 
-    // Initialize MCP Server
-    let mcp_server = mcp::server::McpServer::new(manager.clone());
-    let app = mcp_server.router();
+    // Persist chat messages; told to flush and stop once the daemon shuts down.
+    // The same signal ends SSE streams with a shutdown event.
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let persister_handle = {
+        let store = store.clone();
+        let rx = bus.subscribe();
+        let shutdown_rx = shutdown_rx.clone();
+        tokio::spawn(async move { store.persist_messages(rx, shutdown_rx).await })
+    };
+
+    // SIGHUP asks everything that supports it to re-read the config file
+    #[cfg(unix)]
+    {
+        let bus = bus.clone();
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                info!("Received SIGHUP, reloading config");
+                bus.publish(bus::Event::ConfigChanged);
+            }
+        });
+    }
 
-    let port = 3000;
-    info!("Starting MCP server on port {}", port);
+    // Pre-warm configured projects in the background; failures are only logged
+    if !config.startup_projects.is_empty() {
+        let manager_clone = manager.clone();
+        let projects = config.startup_projects.clone();
+        tokio::spawn(async move { manager_clone.prewarm_projects(projects).await });
+    }
 
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+    // Spawn the scheduler in the background; it is restarted if it dies
+    let scheduler_handle = tokio::spawn(supervise_scheduler(manager.clone()));
 
-    // Initialize Telegram Interface if token is present
-    let telegram_interface = {
-        if std::env::var("TELOXIDE_TOKEN").is_ok() || std::env::var("TELEGRAM_BOT_TOKEN").is_ok() {
-            Some(interface::telegram::TelegramInterface::new(
+    // Initialize MCP Server
+    let mcp_server = mcp::server::McpServer::new(manager.clone(), bus.clone())
+        .with_store(store.clone())
+        .with_max_body_bytes(config.max_body_bytes)
+        .with_request_limits(config.request_limits())
+        .with_shutdown(shutdown_rx.clone());
+    let mut app = mcp_server.router();
+
+    // Initialize Slack Interface if credentials are present
+    let slack_interface = match (&config.slack.bot_token, &config.slack.signing_secret) {
+        (Some(bot_token), Some(signing_secret)) => {
+            let slack = interface::slack::SlackInterface::new(
                 bus.clone(),
                 manager.clone(),
-                Arc::new(store.clone()),
-            ))
-        } else {
-            info!("No Telegram token found, skipping Telegram bot startup.");
+                &outbound,
+                bot_token.clone(),
+                signing_secret.clone(),
+            );
+            app = app.merge(slack.router());
+            Some(slack)
+        }
+        _ => {
+            info!("No Slack credentials found, skipping Slack startup.");
             None
         }
     };
 
+    // Fail at startup, not on the first connection, if the certificates are unusable
+    let tls_config = tls::server_config(&config.tls)?;
+    info!(
+        "Starting MCP server on {}://{}:{}{}",
+        if tls_config.is_some() {
+            "https"
+        } else {
+            "http"
+        },
+        config.bind_address,
+        config.port,
+        if config.tls.client_ca.is_some() {
+            " (client certificates required)"
+        } else {
+            ""
+        }
+    );
+
+    let listener =
+        tokio::net::TcpListener::bind((config.bind_address.as_str(), config.port)).await?;
+    // Stops accepting connections on the shutdown signal and lets open ones finish
+    let server_shutdown = shutdown_rx.clone();
+    let mut server = tokio::spawn(async move {
+        match tls_config {
+            Some(tls_config) => {
+                let handle = axum_server::Handle::new();
+                let draining = handle.clone();
+                tokio::spawn(async move {
+                    shutdown_requested(server_shutdown).await;
+                    draining.graceful_shutdown(Some(SERVER_DRAIN_TIMEOUT));
+                });
+                axum_server::from_tcp_rustls(
+                    listener.into_std()?,
+                    axum_server::tls_rustls::RustlsConfig::from_config(tls_config),
+                )
+                .handle(handle)
+                .serve(app.into_make_service())
+                .await
+            }
+            None => {
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(shutdown_requested(server_shutdown))
+                    .await
+            }
+        }
+    });
+
+    // One Telegram interface per configured bot; they share the bus, manager and store
+    let telegram_interfaces: Vec<_> = config
+        .telegram_bots()
+        .into_iter()
+        .map(|bot_config| {
+            interface::telegram::TelegramInterface::new(
+                bus.clone(),
+                manager.clone(),
+                store.clone(),
+                config.clone(),
+                bot_config,
+                outbound.clone(),
+            )
+        })
+        .collect();
+    if telegram_interfaces.is_empty() {
+        info!("No Telegram token found, skipping Telegram bot startup.");
+    }
+
     // We need to manage the lifetimes and async tasks properly.
     // We'll use a JoinSet or just separate spawns.
 
+    let offline_notifiers = telegram_interfaces.clone();
     let telegram_handle = tokio::spawn(async move {
-        if let Some(telegram) = telegram_interface {
+        if telegram_interfaces.is_empty() {
+            // Keep the task alive but doing nothing if disabled, or just exit.
+            // Exiting is fine.
+            std::future::pending::<()>().await;
+        }
+        let bots = telegram_interfaces.iter().map(|telegram| async move {
             if let Err(e) = telegram.run().await {
                 error!("Telegram bot stopped with error: {}", e);
             }
+        });
+        futures::future::join_all(bots).await;
+    });
+
+    let slack_handle = tokio::spawn(async move {
+        if let Some(slack) = slack_interface {
+            if let Err(e) = slack.run().await {
+                error!("Slack interface stopped with error: {}", e);
+            }
         } else {
-            // Keep the task alive but doing nothing if disabled, or just exit.
-            // Exiting is fine.
             std::future::pending::<()>().await;
         }
     });
 
+    let outbound_handle = tokio::spawn(async move { outbound.run().await });
+
     // Run both the scheduler and the web server
+    let shutdown = shutdown_signal()?;
+    let mut server_stopped = false;
     tokio::select! {
-        _ = tokio::signal::ctrl_c() => {
-            info!("Received Ctrl+C, shutting down...");
+        signal = shutdown => {
+            info!("Received {}, shutting down...", signal);
         }
         _ = scheduler_handle => {
-            info!("Scheduler stopped unexpectedly");
+            error!("Scheduler supervisor stopped unexpectedly");
         }
-        res = axum::serve(listener, app) => {
-            if let Err(e) = res {
-                info!("Server stopped with error: {}", e);
+        res = &mut server => {
+            server_stopped = true;
+            match res {
+                Ok(Err(e)) => info!("Server stopped with error: {}", e),
+                Err(e) => error!("Server task failed: {}", e),
+                Ok(Ok(())) => {}
             }
         }
         _ = telegram_handle => {
              error!("Telegram handle finished unexpectedly");
         }
+        _ = slack_handle => {
+             error!("Slack handle finished unexpectedly");
+        }
+        _ = outbound_handle => {
+             error!("Outbound router finished unexpectedly");
+        }
+    }
+
+    // Signal shutdown first: the server stops accepting connections, SSE
+    // streams say goodbye and the persister stores what it already received
+    let _ = shutdown_tx.send(true);
+
+    let notify_offline = futures::future::join_all(
+        offline_notifiers
+            .iter()
+            .map(|telegram| telegram.notify_offline()),
+    );
+    if tokio::time::timeout(OFFLINE_NOTIFY_TIMEOUT, notify_offline)
+        .await
+        .is_err()
+    {
+        error!("Timed out sending offline notifications");
+    }
+
+    if !server_stopped
+        && tokio::time::timeout(SERVER_DRAIN_TIMEOUT, &mut server)
+            .await
+            .is_err()
+    {
+        error!("Timed out waiting for HTTP requests to finish");
+        server.abort();
+    }
+
+    match tokio::time::timeout(PERSIST_FLUSH_TIMEOUT, persister_handle).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => error!("Message persister failed: {}", e),
+        Err(_) => error!("Timed out flushing messages to the store"),
+    }
+
+    Ok(())
+}
+
+/// Resolves once `shutdown` turns true, or its sender is gone.
+async fn shutdown_requested(mut shutdown: tokio::sync::watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|down| *down).await;
+}
+
+/// Resolves with the signal's name once the daemon is asked to stop: Ctrl+C,
+/// or SIGTERM as sent by systemd and docker. Handlers are installed right away.
+fn shutdown_signal() -> anyhow::Result<impl std::future::Future<Output = &'static str>> {
+    #[cfg(unix)]
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+
+    Ok(async move {
+        #[cfg(unix)]
+        let signal = tokio::select! {
+            _ = tokio::signal::ctrl_c() => "Ctrl+C",
+            _ = terminate.recv() => "SIGTERM",
+        };
+        #[cfg(not(unix))]
+        let signal = {
+            let _ = tokio::signal::ctrl_c().await;
+            "Ctrl+C"
+        };
+        signal
+    })
+}
+
+/// Run the scheduler, restarting it with exponential backoff whenever it
+/// returns or panics. Only returns if the runtime shuts down.
+async fn supervise_scheduler(manager: Arc<manager::Manager>) {
+    supervise(|| {
+        let manager = manager.clone();
+        async move {
+            info!("Starting scheduler...");
+            manager.start_scheduler().await;
+        }
+    })
+    .await
+}
+
+/// Spawn the task `start` makes and start another whenever it returns or
+/// panics, backing off exponentially. Returns if the task is cancelled.
+async fn supervise<F, Fut>(start: F)
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = ()> + Send + 'static,
+{
+    let mut backoff = SCHEDULER_RESTART_MIN;
+    loop {
+        let started = tokio::time::Instant::now();
+        match tokio::spawn(start()).await {
+            Ok(()) => error!("Scheduler stopped unexpectedly"),
+            Err(e) if e.is_panic() => error!("Scheduler panicked: {}", e),
+            Err(e) => {
+                error!("Scheduler task was cancelled: {}", e);
+                return;
+            }
+        }
+
+        // A run that lasted a while was healthy; don't carry its backoff over
+        if started.elapsed() >= SCHEDULER_RESTART_MAX {
+            backoff = SCHEDULER_RESTART_MIN;
+        }
+        info!("Restarting scheduler in {:?}", backoff);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(SCHEDULER_RESTART_MAX);
     }
+}
+
+/// Set up logging: `RUST_LOG` filters (default `info`), `THALASSA_LOG_FORMAT`
+/// picks `full` (default), `compact`, `pretty` or `json` output.
+fn init_logging(to_stderr: bool) -> anyhow::Result<()> {
+    let writer = if to_stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let logging = tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
+        )
+        .with_writer(writer);
 
+    let format = std::env::var("THALASSA_LOG_FORMAT").unwrap_or_default();
+    match format.trim().to_lowercase().as_str() {
+        "" | "full" => logging.init(),
+        "compact" => logging.compact().init(),
+        "pretty" => logging.pretty().init(),
+        "json" => logging.json().init(),
+        other => anyhow::bail!(
+            "Invalid THALASSA_LOG_FORMAT '{}': expected full, compact, pretty or json",
+            other
+        ),
+    }
     Ok(())
 }
+
+/// Value of `--config <file>` / `--config=<file>` on the command line, if given.
+fn config_path_from_args() -> Option<PathBuf> {
+    arg_value("--config").map(PathBuf::from)
+}
+
+/// Value of a `--flag value` or `--flag=value` command line argument.
+fn arg_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+        if let Some(value) = arg
+            .strip_prefix(flag)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test(start_paused = true)]
+    async fn a_panicking_scheduler_is_restarted_with_backoff() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let (started_tx, mut started_rx) = tokio::sync::mpsc::unbounded_channel();
+        let supervisor = tokio::spawn(supervise({
+            let runs = runs.clone();
+            move || {
+                let run = runs.fetch_add(1, Ordering::SeqCst) + 1;
+                let started_tx = started_tx.clone();
+                async move {
+                    started_tx.send(tokio::time::Instant::now()).unwrap();
+                    match run {
+                        1 => panic!("scheduler bug"),
+                        2 => {}
+                        _ => std::future::pending().await,
+                    }
+                }
+            }
+        }));
+
+        let first = started_rx.recv().await.unwrap();
+        let second = started_rx.recv().await.unwrap();
+        let third = started_rx.recv().await.unwrap();
+        // Restarted after a panic and after returning, waiting longer each time
+        assert_eq!(second - first, SCHEDULER_RESTART_MIN);
+        assert_eq!(third - second, SCHEDULER_RESTART_MIN * 2);
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert!(!supervisor.is_finished());
+        supervisor.abort();
+    }
+}
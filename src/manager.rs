@@ -1,68 +1,583 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use mothership::runtime::Runtime;
+use notify::Watcher;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{oneshot, Semaphore};
 use tokio::task;
+use tracing::{error, info, warn};
+use uuid::Uuid;
 
-use crate::agent::bridge::AgentSession;
-use crate::bus::EventBus;
-use crate::entity::{EntityId, Role};
+use crate::agent::bridge::{AgentBinaryMissing, AgentSession, SessionStatus};
+use crate::agent::redact::Redactor;
+use crate::bus::{Event, EventBus, NotificationLevel, ScheduledPrompt};
+use crate::chat::{ChatMessage, PROJECT_NAME_KEY};
+use crate::config::{Config, ResourceLimits};
+use crate::entity::{AgentEntity, Entity, EntityId, Role};
+use crate::recurrence::Recurrence;
+use crate::store::{Job, Store, JOB_KIND_PROMPT};
+
+/// How often the scheduler looks for due jobs, and how many it takes at a time.
+const SCHEDULER_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const SCHEDULER_BATCH_SIZE: i64 = 16;
+
+/// Failed scheduled jobs are retried after `JOB_RETRY_BASE`, doubling each time,
+/// until this many runs have failed.
+const JOB_MAX_ATTEMPTS: u32 = 3;
+const JOB_RETRY_BASE: Duration = Duration::from_secs(30);
+
+/// Most jobs [`Manager::list_jobs`] returns.
+const JOB_LIST_LIMIT: i64 = 50;
+
+/// Quiet time after a change in `projects_watch_dir` before the project list is
+/// re-read, so a checkout or delete (a burst of events) is handled once.
+const PROJECTS_WATCH_SETTLE: Duration = Duration::from_millis(500);
+
+/// What the manager and agent sessions need from the container runtime.
+/// Implemented by mothership's [`Runtime`]; tests substitute a fake.
+pub trait ProjectRuntime: Send + Sync {
+    fn list_projects(&self) -> Result<Vec<String>>;
+    /// Start the project's container, capped at `limits` if given. If the
+    /// limits can't be applied the container is stopped again and the error is
+    /// a [`ResourceLimitsRejected`].
+    fn launch(&self, name: &str, limits: Option<&ResourceLimits>) -> Result<()>;
+    /// Run `cmd` in the project's container and return its stdout.
+    fn exec_capture(&self, name: &str, cmd: &str) -> Result<String>;
+    /// Start `cmd` in the project's container with piped stdio.
+    fn spawn_exec(&self, name: &str, cmd: &str) -> Result<std::process::Child>;
+    /// Cheap liveness ping. Blocks.
+    fn healthy(&self) -> bool;
+}
+
+impl ProjectRuntime for Runtime {
+    fn list_projects(&self) -> Result<Vec<String>> {
+        Runtime::list_projects(self).context("mothership failed to list projects")
+    }
+
+    fn launch(&self, name: &str, limits: Option<&ResourceLimits>) -> Result<()> {
+        Runtime::launch(self, name).context("mothership failed to launch the project")?;
+        let Some(limits) = limits else {
+            return Ok(());
+        };
+
+        // mothership's own `launch` takes no limits, so they are set on the new
+        // container with `docker update`; docker names its host after its short id
+        let container = Runtime::exec_capture(self, name, "hostname")
+            .context("Failed to identify the project's container")?
+            .trim()
+            .to_string();
+        let mut update = Command::new("docker");
+        update.arg("update");
+        if let Some(cpus) = limits.cpus {
+            update.arg("--cpus").arg(cpus.to_string());
+        }
+        if let Some(memory) = &limits.memory {
+            update.arg("--memory").arg(memory);
+            update.arg("--memory-swap").arg(memory);
+        }
+        let output = update
+            .arg(&container)
+            .stdout(Stdio::null())
+            .output()
+            .context("Failed to run docker update")?;
+
+        if !output.status.success() {
+            // Never leave it running uncapped
+            let stopped = Command::new("docker")
+                .arg("stop")
+                .arg(&container)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+            if !stopped.is_ok_and(|status| status.success()) {
+                error!("Failed to stop {} after its limits were rejected", name);
+            }
+            return Err(ResourceLimitsRejected {
+                project: name.to_string(),
+                message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn exec_capture(&self, name: &str, cmd: &str) -> Result<String> {
+        Runtime::exec_capture(self, name, cmd).context("mothership failed to exec")
+    }
+
+    fn spawn_exec(&self, name: &str, cmd: &str) -> Result<std::process::Child> {
+        Runtime::spawn_exec(self, name, cmd).context("mothership failed to exec")
+    }
+
+    fn healthy(&self) -> bool {
+        // mothership runs projects on the local docker daemon. Its socket answers
+        // `/_ping` without the cost of starting `docker info`, which is left for
+        // daemons reached some other way
+        match docker_socket() {
+            Some(socket) => docker_ping(&socket).unwrap_or(false),
+            None => Command::new("docker")
+                .arg("info")
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .map(|status| status.success())
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Longest a docker `/_ping` may take before the daemon counts as down.
+const DOCKER_PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The docker daemon's unix socket: from `DOCKER_HOST`, or the default one.
+/// None if `DOCKER_HOST` points somewhere else, e.g. over TCP.
+fn docker_socket() -> Option<String> {
+    match std::env::var("DOCKER_HOST") {
+        Ok(host) if !host.is_empty() => host.strip_prefix("unix://").map(str::to_string),
+        _ => Some("/var/run/docker.sock".to_string()),
+    }
+}
+
+/// Whether the docker daemon listening on `socket` answers its `/_ping`.
+fn docker_ping(socket: &str) -> std::io::Result<bool> {
+    let mut stream = std::os::unix::net::UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(DOCKER_PING_TIMEOUT))?;
+    stream.set_write_timeout(Some(DOCKER_PING_TIMEOUT))?;
+    stream.write_all(b"GET /_ping HTTP/1.0\r\nHost: docker\r\n\r\n")?;
+    let mut status = String::new();
+    std::io::BufReader::new(stream).read_line(&mut status)?;
+    Ok(status.split_whitespace().nth(1) == Some("200"))
+}
+
+/// Returned when the container runtime (docker daemon) can't be reached.
+#[derive(Debug)]
+pub struct RuntimeUnavailable;
+
+impl fmt::Display for RuntimeUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Container runtime unavailable: is the docker daemon running?"
+        )
+    }
+}
+
+impl std::error::Error for RuntimeUnavailable {}
+
+/// Returned when asked to launch a project the runtime doesn't know about.
+#[derive(Debug)]
+pub struct ProjectNotFound(pub String);
+
+impl fmt::Display for ProjectNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Project '{}' not found", self.0)
+    }
+}
+
+impl std::error::Error for ProjectNotFound {}
+
+/// Returned by [`Manager::exec_command`] when the command was cancelled.
+#[derive(Debug)]
+pub struct ExecCancelled;
+
+impl fmt::Display for ExecCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Command was cancelled")
+    }
+}
+
+impl std::error::Error for ExecCancelled {}
+
+/// Returned when a file path given for a project points outside its checkout.
+#[derive(Debug)]
+pub struct PathOutsideProject {
+    pub project: String,
+    pub path: String,
+}
+
+impl fmt::Display for PathOutsideProject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Path {:?} is outside the {} project directory",
+            self.path, self.project
+        )
+    }
+}
+
+impl std::error::Error for PathOutsideProject {}
+
+/// Exit status of the in-container check that a path, with symlinks resolved,
+/// still lies inside the project directory.
+const PATH_ESCAPES_STATUS: i32 = 3;
+
+/// Shell prefix reading the project root and the target path, one line each,
+/// from stdin into `$root` and `$f`. Paths never become part of the command.
+const READ_PATHS: &str = "IFS= read -r root && IFS= read -r f";
+
+/// Longest file name an upload is stored under, and how many `-N` suffixes are
+/// tried when that name is taken.
+const MAX_UPLOAD_NAME_CHARS: usize = 100;
+const MAX_UPLOAD_NAME_ATTEMPTS: u32 = 100;
+
+/// Returned when docker refuses a project's configured `project_limits`. The
+/// container is not left running.
+#[derive(Debug)]
+pub struct ResourceLimitsRejected {
+    pub project: String,
+    pub message: String,
+}
+
+impl fmt::Display for ResourceLimitsRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the container runtime rejected its resource limits ({}). Check project_limits.{} in the config.",
+            self.message, self.project
+        )
+    }
+}
+
+impl std::error::Error for ResourceLimitsRejected {}
+
+/// A project's container as [`Manager::project_info`] sees it.
+#[derive(Debug, Clone)]
+pub struct ProjectInfo {
+    pub name: String,
+    pub running: bool,
+    /// The resource limits its container was launched with. `None` if it runs
+    /// uncapped, or was started before this daemon and its limits are unknown
+    pub limits: Option<ResourceLimits>,
+}
+
+/// Why the runtime failed to start a project's container, classified from its
+/// error text so interfaces can say what to do about it. Each variant keeps
+/// the runtime's original message, which is shown along with the advice.
+#[derive(Debug)]
+pub enum LaunchError {
+    /// The project's image isn't built or can't be pulled
+    ImageMissing(String),
+    /// A port the container publishes is taken on the host
+    PortConflict(String),
+    /// The host ran out of disk or memory
+    OutOfResources(String),
+    Other(String),
+}
+
+impl LaunchError {
+    fn classify(err: &anyhow::Error) -> Self {
+        let raw = format!("{:#}", err);
+        let lower = raw.to_lowercase();
+        let mentions = |needles: &[&str]| needles.iter().any(|n| lower.contains(n));
+
+        if mentions(&[
+            "no such image",
+            "pull access denied",
+            "manifest unknown",
+            "repository does not exist",
+        ]) {
+            LaunchError::ImageMissing(raw)
+        } else if mentions(&["port is already allocated", "address already in use"]) {
+            LaunchError::PortConflict(raw)
+        } else if mentions(&[
+            "no space left on device",
+            "cannot allocate memory",
+            "out of memory",
+        ]) {
+            LaunchError::OutOfResources(raw)
+        } else {
+            LaunchError::Other(raw)
+        }
+    }
+}
+
+impl fmt::Display for LaunchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LaunchError::ImageMissing(raw) => write!(
+                f,
+                "its container image is missing ({}). Build the project's image (or check the registry login) and try again.",
+                raw
+            ),
+            LaunchError::PortConflict(raw) => write!(
+                f,
+                "a port it needs is already in use on the host ({}). Stop whatever holds it (often another copy of this project) and try again.",
+                raw
+            ),
+            LaunchError::OutOfResources(raw) => write!(
+                f,
+                "the host is out of disk space or memory ({}). Free some up (e.g. `docker system prune`) and try again.",
+                raw
+            ),
+            LaunchError::Other(raw) => write!(f, "{}", raw),
+        }
+    }
+}
+
+impl std::error::Error for LaunchError {}
+
+/// What to tell a user whose launch of `project_name` failed with `err`.
+pub fn launch_failure_message(project_name: &str, err: &anyhow::Error) -> String {
+    if let Some(not_found) = err.downcast_ref::<ProjectNotFound>() {
+        return format!("{}.", not_found);
+    }
+    if err.downcast_ref::<RuntimeUnavailable>().is_some() {
+        return format!("Cannot launch {}: {}", project_name, err);
+    }
+    if let Some(rejected) = err.downcast_ref::<ResourceLimitsRejected>() {
+        return format!("Could not start {}: {}", project_name, rejected);
+    }
+    if let Some(missing) = err.downcast_ref::<AgentBinaryMissing>() {
+        return format!(
+            "Could not start the agent for {}: {}. Install it in the project's image, or point `agent_command` at it.",
+            project_name, missing
+        );
+    }
+    match err.downcast_ref::<LaunchError>() {
+        Some(launch) => format!("Could not start {}: {}", project_name, launch),
+        None => format!("Failed to launch {}: {}", project_name, err),
+    }
+}
 
 pub struct Manager {
-    runtime: Arc<Runtime>,
+    runtime: Arc<dyn ProjectRuntime>,
     event_bus: Arc<EventBus>,
+    config: Arc<Config>,
+    store: Arc<Store>,
     scheduler: Scheduler,
+    // Bounds concurrent container + agent boots
+    launch_limit: Semaphore,
+    // Last project list from the runtime and when it was fetched
+    project_cache: Mutex<Option<(tokio::time::Instant, Vec<String>)>>,
+    // Last runtime health check and when it ran
+    runtime_health: Mutex<Option<(tokio::time::Instant, bool)>>,
+    // Reply redaction rules shared by every agent session
+    redactor: Arc<Redactor>,
+    // Limits each container this manager launched actually runs with
+    effective_limits: Mutex<HashMap<String, ResourceLimits>>,
     sessions: Arc<Mutex<HashMap<String, Arc<AgentSession>>>>, // Changed from Mutex<AgentSession> to AgentSession since AgentSession is mostly read-only/uses internal locking or async
                                                               // Wait, AgentSession has async methods. But it doesn't seem to have mutable state that needs external locking after initialization.
                                                               // The `start()` method takes &self.
 }
 
 impl Manager {
-    pub fn new(event_bus: Arc<EventBus>) -> Result<Self> {
-        let runtime = Runtime::new()?;
+    pub fn new(event_bus: Arc<EventBus>, config: Arc<Config>, store: Arc<Store>) -> Result<Self> {
+        Self::with_runtime(Arc::new(Runtime::new()?), event_bus, config, store)
+    }
+
+    /// A manager on the given runtime instead of mothership's.
+    pub fn with_runtime(
+        runtime: Arc<dyn ProjectRuntime>,
+        event_bus: Arc<EventBus>,
+        config: Arc<Config>,
+        store: Arc<Store>,
+    ) -> Result<Self> {
+        let redactor = Redactor::new(&config.redact_patterns)?;
         Ok(Self {
-            runtime: Arc::new(runtime),
-            scheduler: Scheduler::new(),
+            runtime,
+            scheduler: Scheduler::new(store.clone(), event_bus.clone()),
+            store,
+            launch_limit: Semaphore::new(config.max_concurrent_launches.max(1)),
+            project_cache: Mutex::new(None),
+            runtime_health: Mutex::new(None),
+            redactor: Arc::new(redactor),
             event_bus,
+            config,
+            effective_limits: Mutex::new(HashMap::new()),
             sessions: Arc::new(Mutex::new(HashMap::new())),
         })
     }
 
+    /// Project names, served from a short-lived cache (`project_cache_ttl_secs`).
     pub async fn list_projects(&self) -> Result<Vec<String>> {
+        let ttl = Duration::from_secs(self.config.project_cache_ttl_secs);
+        if let Some((fetched_at, projects)) = &*self.project_cache.lock().unwrap() {
+            if fetched_at.elapsed() < ttl {
+                return Ok(projects.clone());
+            }
+        }
+
+        self.refresh_projects().await
+    }
+
+    /// Fetch the project list from the runtime, bypassing and replacing the cache.
+    pub async fn refresh_projects(&self) -> Result<Vec<String>> {
         let runtime = self.runtime.clone();
-        task::spawn_blocking(move || runtime.list_projects()).await?
+        let projects = task::spawn_blocking(move || runtime.list_projects()).await??;
+
+        *self.project_cache.lock().unwrap() = Some((tokio::time::Instant::now(), projects.clone()));
+        Ok(projects)
     }
 
-    pub async fn launch_project(&self, name: String) -> Result<()> {
+    fn invalidate_project_cache(&self) {
+        self.project_cache.lock().unwrap().take();
+    }
+
+    /// Cheap liveness ping of the container runtime backing mothership. The
+    /// answer is reused for `runtime_health_ttl_secs`.
+    pub async fn runtime_healthy(&self) -> bool {
+        let ttl = Duration::from_secs(self.config.runtime_health_ttl_secs);
+        if let Some((checked_at, healthy)) = *self.runtime_health.lock().unwrap() {
+            if checked_at.elapsed() < ttl {
+                return healthy;
+            }
+        }
+
         let runtime = self.runtime.clone();
-        let name_clone = name.clone();
-        task::spawn_blocking(move || runtime.launch(&name_clone)).await??;
+        let healthy = task::spawn_blocking(move || runtime.healthy())
+            .await
+            .unwrap_or(false);
+        *self.runtime_health.lock().unwrap() = Some((tokio::time::Instant::now(), healthy));
+        healthy
+    }
+
+    /// Fail with [`ProjectNotFound`] unless the runtime lists `name`. A miss in
+    /// the cached list is double-checked against a fresh one.
+    async fn ensure_project_exists(&self, name: &str) -> Result<()> {
+        let known = |projects: Vec<String>| projects.iter().any(|p| p == name);
+        if known(self.list_projects().await?) || known(self.refresh_projects().await?) {
+            Ok(())
+        } else {
+            Err(ProjectNotFound(name.to_string()).into())
+        }
+    }
+
+    /// Whether the project's container is up, i.e. the runtime can exec into it.
+    pub async fn is_running(&self, name: &str) -> bool {
+        let runtime = self.runtime.clone();
+        let name = name.to_string();
+        task::spawn_blocking(move || runtime.exec_capture(&name, "true").is_ok())
+            .await
+            .unwrap_or(false)
+    }
+
+    #[tracing::instrument(skip(self, name), fields(project = %name))]
+    pub async fn launch_project(&self, name: String) -> Result<()> {
+        if !self.runtime_healthy().await {
+            return Err(RuntimeUnavailable.into());
+        }
+
+        self.ensure_project_exists(&name).await?;
+
+        let _permit = self.launch_limit.acquire().await?;
+
+        // A container left running (e.g. its agent session was dropped) is reused
+        // rather than launched again next to it
+        if self.is_running(&name).await {
+            info!("Container for {} is already running, reusing it", name);
+        } else {
+            let runtime = self.runtime.clone();
+            let name_clone = name.clone();
+            let limits = self.resource_limits(&name).cloned();
+            let launched = task::spawn_blocking(move || {
+                runtime.launch(&name_clone, limits.as_ref())?;
+                Ok::<_, anyhow::Error>(limits)
+            })
+            .await?;
+            // Launching may have built or registered the project
+            self.invalidate_project_cache();
+            match launched {
+                Ok(limits) => {
+                    let mut effective = self.effective_limits.lock().unwrap();
+                    match limits {
+                        Some(limits) => {
+                            info!("Launched {} with resource limits: {}", name, limits);
+                            effective.insert(name.clone(), limits);
+                        }
+                        None => {
+                            effective.remove(&name);
+                        }
+                    }
+                }
+                Err(e) if e.is::<ResourceLimitsRejected>() => {
+                    error!("Runtime rejected the limits of {}: {:#}", name, e);
+                    self.effective_limits.lock().unwrap().remove(&name);
+                    return Err(e);
+                }
+                Err(e) => {
+                    let classified = LaunchError::classify(&e);
+                    error!("Runtime failed to launch {}: {:#}", name, e);
+                    return Err(classified.into());
+                }
+            }
+        }
 
         self.start_agent_session(name).await?;
 
         Ok(())
     }
 
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The configured `project_limits` of `name`, if any.
+    pub fn resource_limits(&self, name: &str) -> Option<&ResourceLimits> {
+        self.config
+            .project_limits
+            .get(name)
+            .filter(|limits| !limits.is_empty())
+    }
+
+    /// What is known about `name`'s container.
+    pub async fn project_info(&self, name: &str) -> ProjectInfo {
+        ProjectInfo {
+            name: name.to_string(),
+            running: self.is_running(name).await,
+            limits: self.effective_limits.lock().unwrap().get(name).cloned(),
+        }
+    }
+
+    /// Launch `projects` ahead of time so their agents are ready immediately.
+    /// Failures are logged and don't affect the other projects.
+    pub async fn prewarm_projects(&self, projects: Vec<String>) {
+        let launches = projects.into_iter().map(|name| async move {
+            info!("Pre-warming project {}", name);
+            match self.launch_project(name.clone()).await {
+                Ok(()) => info!("Pre-warmed project {}", name),
+                Err(e) => error!("Failed to pre-warm project {}: {:#}", name, e),
+            }
+        });
+        futures::future::join_all(launches).await;
+    }
+
+    #[tracing::instrument(skip(self, project_name), fields(project = %project_name))]
     pub async fn start_agent_session(&self, project_name: String) -> Result<()> {
         // Scope the lock so it is dropped before awaiting
-        {
-            let sessions = self.sessions.lock().unwrap();
-            if sessions.contains_key(&project_name) {
+        let existing = self.sessions.lock().unwrap().get(&project_name).cloned();
+        if let Some(existing) = existing {
+            let status = existing.status().await;
+            if status.ready {
                 return Ok(());
             }
+            // A session whose ACP connection never came up is useless; replace it
+            warn!(
+                "Replacing agent session {} for {} that is not ready (ACP session: {:?})",
+                status.session_id, status.project_name, status.acp_session_id
+            );
+            existing.stop().await;
+            self.sessions.lock().unwrap().remove(&project_name);
         }
 
-        let agent_id = EntityId::new(
-            format!("agent-{}", project_name),
-            "Mothership Agent",
-            Role::Agent,
-        );
+        let agent_id = Entity::Agent(AgentEntity {
+            project_name: project_name.clone(),
+        })
+        .id();
 
         let session = AgentSession::new(
             project_name.clone(),
             agent_id,
             self.event_bus.clone(),
             self.runtime.clone(),
+            self.config.clone(),
+            self.redactor.clone(),
+            self.store.clone(),
         );
 
         session.start().await?;
@@ -74,27 +589,1654 @@ impl Manager {
         Ok(())
     }
 
-    pub async fn exec_command(&self, name: String, cmd: String) -> Result<String> {
+    /// Tear down the agent session of `project_name`. Its container keeps
+    /// running and is reused by the next launch.
+    pub async fn stop_project(&self, project_name: &str) -> Result<()> {
+        let session = self.sessions.lock().unwrap().remove(project_name);
+        let Some(session) = session else {
+            anyhow::bail!("No agent session is running for {}", project_name);
+        };
+        session.stop().await;
+
+        self.event_bus.publish(Event::SystemNotification {
+            level: NotificationLevel::Warning,
+            message: format!("Stopped the agent for {}", project_name),
+            target: None,
+        });
+        Ok(())
+    }
+
+    /// Tear down every agent session. Returns how many were stopped.
+    pub async fn stop_all_sessions(&self) -> usize {
+        let sessions: Vec<Arc<AgentSession>> = {
+            let mut sessions = self.sessions.lock().unwrap();
+            sessions.drain().map(|(_, session)| session).collect()
+        };
+
+        for session in &sessions {
+            session.stop().await;
+        }
+
+        if !sessions.is_empty() {
+            self.event_bus.publish(Event::SystemNotification {
+                level: NotificationLevel::Warning,
+                message: format!("Stopped {} agent sessions", sessions.len()),
+                target: None,
+            });
+        }
+        sessions.len()
+    }
+
+    pub async fn session_status(&self, project_name: &str) -> Option<SessionStatus> {
+        let session = self.sessions.lock().unwrap().get(project_name).cloned()?;
+        Some(session.status().await)
+    }
+
+    /// Status of every agent session, ordered by project name.
+    pub async fn all_session_statuses(&self) -> Vec<SessionStatus> {
+        let sessions: Vec<Arc<AgentSession>> =
+            self.sessions.lock().unwrap().values().cloned().collect();
+        let mut statuses = futures::future::join_all(sessions.iter().map(|s| s.status())).await;
+        statuses.sort_by(|a, b| a.project_name.cmp(&b.project_name));
+        statuses
+    }
+
+    /// Interrupt the turn `project_name`'s agent is working on.
+    /// Returns false if there is no session or it is idle.
+    pub async fn cancel_turn(&self, project_name: &str) -> Result<bool> {
+        let session = self.sessions.lock().unwrap().get(project_name).cloned();
+        match session {
+            Some(session) => session.cancel_turn().await,
+            None => Ok(false),
+        }
+    }
+
+    /// Whether `project_name` has an agent session that can take prompts right now.
+    pub async fn agent_ready(&self, project_name: &str) -> bool {
+        self.session_status(project_name)
+            .await
+            .is_some_and(|status| status.ready)
+    }
+
+    /// Run `cmd` in the project's container and return its stdout. Firing `cancel`
+    /// kills the exec session running it and fails with [`ExecCancelled`].
+    pub async fn exec_command(
+        &self,
+        name: String,
+        cmd: String,
+        mut cancel: oneshot::Receiver<()>,
+    ) -> Result<String> {
         let runtime = self.runtime.clone();
-        task::spawn_blocking(move || runtime.exec_capture(&name, &cmd)).await?
+        let mut child = task::spawn_blocking(move || runtime.spawn_exec(&name, &cmd)).await??;
+        let stdout = child.stdout.take().context("Failed to take stdout")?;
+        let stderr = child.stderr.take();
+        let child = Arc::new(Mutex::new(child));
+
+        // Drain stdout and stderr together so neither pipe can fill up and stall the command
+        let output = task::spawn_blocking(move || -> Result<(Vec<u8>, Vec<u8>)> {
+            let stderr_reader = stderr.map(|mut stderr| {
+                std::thread::spawn(move || {
+                    let mut bytes = Vec::new();
+                    let _ = stderr.read_to_end(&mut bytes);
+                    bytes
+                })
+            });
+            let mut out = Vec::new();
+            let mut stdout = stdout;
+            stdout
+                .read_to_end(&mut out)
+                .context("Failed to read command output")?;
+            let err = stderr_reader
+                .and_then(|reader| reader.join().ok())
+                .unwrap_or_default();
+            Ok((out, err))
+        });
+        tokio::pin!(output);
+
+        let (stdout, stderr) = tokio::select! {
+            output = &mut output => output??,
+            Ok(()) = &mut cancel => {
+                info!("Cancelling exec");
+                let _ = child.lock().unwrap().kill();
+                let _ = output.await;
+                let _ = child.lock().unwrap().wait();
+                return Err(ExecCancelled.into());
+            }
+        };
+
+        let status = task::spawn_blocking(move || child.lock().unwrap().wait()).await??;
+        if !status.success() {
+            anyhow::bail!(
+                "Command failed ({}): {}",
+                status,
+                String::from_utf8_lossy(&stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    }
+
+    /// Run `cmd` in the project's container, handing each line of stdout and
+    /// stderr (interleaved, newline included) to `on_output` as it arrives.
+    /// Returns the exit status; a non-zero one is not an error here.
+    pub async fn exec_command_streaming<F>(
+        &self,
+        name: String,
+        cmd: String,
+        mut on_output: F,
+    ) -> Result<std::process::ExitStatus>
+    where
+        F: FnMut(&str) + Send,
+    {
+        let runtime = self.runtime.clone();
+        let mut child = task::spawn_blocking(move || runtime.spawn_exec(&name, &cmd)).await??;
+        let stdout = child.stdout.take().context("Failed to take stdout")?;
+        let stderr = child.stderr.take();
+
+        // Both pipes feed one channel, which closes once both reach EOF
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+        if let Some(stderr) = stderr {
+            let tx = tx.clone();
+            task::spawn_blocking(move || forward_lines(stderr, tx));
+        }
+        task::spawn_blocking(move || forward_lines(stdout, tx));
+
+        while let Some(line) = rx.recv().await {
+            on_output(&line);
+        }
+        Ok(task::spawn_blocking(move || child.wait()).await??)
+    }
+
+    /// Write `bytes` to `uploads/` in the project's directory inside its container,
+    /// under a sanitized `file_name` made unique with a `-N` suffix if taken.
+    /// Returns the path of the written file as seen from inside the container.
+    pub async fn upload_file(
+        &self,
+        project_name: String,
+        file_name: String,
+        bytes: Vec<u8>,
+    ) -> Result<String> {
+        let root = self.config.project_dir(&project_name);
+        let target = format!("{}/uploads/{}", root, sanitize_upload_name(&file_name));
+        // Creating the file with noclobber first claims its name atomically
+        let cmd = format!(
+            "{READ_PATHS} && dir=$(realpath -m -- \"$(dirname -- \"$f\")\") && case \"$dir\" in \"$root\"/*) ;; *) exit {status} ;; esac && mkdir -p -- \"$dir\" && name=${{f##*/}} && stem=${{name%.*}} && ext=${{name#\"$stem\"}} && n=1 && f=\"$dir/$name\" && until (set -C; : > \"$f\") 2>/dev/null; do [ $n -lt {max} ] || exit 1; f=\"$dir/$stem-$n$ext\"; n=$((n+1)); done && cat > \"$f\" && printf '%s' \"$f\"",
+            status = PATH_ESCAPES_STATUS,
+            max = MAX_UPLOAD_NAME_ATTEMPTS
+        );
+
+        let runtime = self.runtime.clone();
+        task::spawn_blocking(move || -> Result<String> {
+            let mut child = runtime.spawn_exec(&project_name, &cmd)?;
+            let mut stdin = child.stdin.take().context("Failed to take stdin")?;
+            // The shell may exit before reading, e.g. when uploads/ leads outside
+            let written = stdin
+                .write_all(format!("{}\n{}\n", root, target).as_bytes())
+                .and_then(|()| stdin.write_all(&bytes));
+            // Close stdin so `cat` sees EOF
+            drop(stdin);
+
+            let mut path = String::new();
+            if let Some(mut stdout) = child.stdout.take() {
+                stdout.read_to_string(&mut path)?;
+            }
+            let status = child.wait()?;
+            if status.code() == Some(PATH_ESCAPES_STATUS) {
+                return Err(PathOutsideProject {
+                    project: project_name,
+                    path: "uploads".to_string(),
+                }
+                .into());
+            }
+            written.context("Failed to stream upload into container")?;
+            if !status.success() || path.is_empty() {
+                anyhow::bail!("Writing upload failed: {}", status);
+            }
+            Ok(path)
+        })
+        .await?
+    }
+
+    /// Read `path`, relative to the project's checkout or absolute within it,
+    /// refusing paths (symlinks included) that lead outside the checkout.
+    pub async fn read_project_file(
+        &self,
+        project_name: String,
+        path: String,
+        max_bytes: u64,
+    ) -> Result<Vec<u8>> {
+        let runtime = self.runtime.clone();
+        let config = self.config.clone();
+        task::spawn_blocking(move || {
+            read_project_file_blocking(runtime.as_ref(), &config, &project_name, &path, max_bytes)
+        })
+        .await?
+    }
+
+    /// Replace the file at `path` (as for [`Manager::read_project_file`]) with
+    /// `bytes`, creating missing parent directories.
+    pub async fn write_project_file(
+        &self,
+        project_name: String,
+        path: String,
+        bytes: Vec<u8>,
+    ) -> Result<()> {
+        let root = self.config.project_dir(&project_name);
+        let target = confine_to_project(&root, &project_name, &path)?;
+        // Existing parts of the path may be symlinks; resolve them before writing
+        let cmd = format!(
+            "{READ_PATHS} && f=$(realpath -m -- \"$f\") && case \"$f\" in \"$root\"/*) mkdir -p -- \"$(dirname -- \"$f\")\" && exec cat > \"$f\" ;; *) exit {status} ;; esac",
+            status = PATH_ESCAPES_STATUS
+        );
+
+        let runtime = self.runtime.clone();
+        let label = path.clone();
+        task::spawn_blocking(move || -> Result<()> {
+            let mut child = runtime.spawn_exec(&project_name, &cmd)?;
+            let mut stdin = child.stdin.take().context("Failed to take stdin")?;
+            // The shell may exit before reading, e.g. when the path escapes
+            let written = stdin
+                .write_all(format!("{}\n{}\n", root, target).as_bytes())
+                .and_then(|()| stdin.write_all(&bytes));
+            drop(stdin);
+
+            let status = child.wait()?;
+            if status.code() == Some(PATH_ESCAPES_STATUS) {
+                return Err(PathOutsideProject {
+                    project: project_name,
+                    path: label,
+                }
+                .into());
+            }
+            written.context("Failed to stream file into container")?;
+            if !status.success() {
+                anyhow::bail!("Writing {} failed: {}", label, status);
+            }
+            Ok(())
+        })
+        .await?
     }
 
     pub async fn start_scheduler(&self) {
-        self.scheduler.start().await;
+        tokio::join!(
+            self.scheduler.start(),
+            self.consume_scheduled_events(),
+            self.reload_on_config_change(),
+            self.watch_projects_dir()
+        );
+    }
+
+    /// Re-read the project list whenever an entry in `projects_watch_dir` is
+    /// created, removed or renamed, and announce the projects that came or went.
+    async fn watch_projects_dir(&self) {
+        let Some(dir) = self.config.projects_watch_dir.clone() else {
+            return;
+        };
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+                Ok(event) if changes_projects(&event.kind) => {
+                    let _ = tx.send(());
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Project directory watch failed: {}", e),
+            });
+        // Dropping the watcher stops it, so it lives as long as this loop
+        let mut watcher = match watcher {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                error!("Failed to set up project directory watch: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = watcher.watch(&dir, notify::RecursiveMode::NonRecursive) {
+            error!("Failed to watch {}: {}", dir.display(), e);
+            return;
+        }
+        info!("Watching {} for project changes", dir.display());
+
+        let mut known = self.refresh_projects().await.ok();
+        while rx.recv().await.is_some() {
+            tokio::time::sleep(PROJECTS_WATCH_SETTLE).await;
+            while rx.try_recv().is_ok() {}
+
+            let projects = match self.refresh_projects().await {
+                Ok(projects) => projects,
+                Err(e) => {
+                    warn!("Failed to refresh projects after a change: {:#}", e);
+                    // Don't serve the stale list until the TTL runs out
+                    *self.project_cache.lock().unwrap() = None;
+                    continue;
+                }
+            };
+            // Without a baseline there is nothing to compare against yet
+            if let Some(known) = &known {
+                for added in projects.iter().filter(|p| !known.contains(p)) {
+                    info!("Project {} appeared", added);
+                    self.event_bus.publish(Event::SystemNotification {
+                        level: NotificationLevel::Info,
+                        message: format!("New project available: {}", added),
+                        target: None,
+                    });
+                }
+                for removed in known.iter().filter(|p| !projects.contains(p)) {
+                    info!("Project {} disappeared", removed);
+                    self.event_bus.publish(Event::SystemNotification {
+                        level: NotificationLevel::Info,
+                        message: format!("Project removed: {}", removed),
+                        target: None,
+                    });
+                }
+            }
+            known = Some(projects);
+        }
+    }
+
+    /// Re-read the config file on `ConfigChanged` and apply the settings that
+    /// can change at runtime (currently the reply redaction rules).
+    async fn reload_on_config_change(&self) {
+        let mut rx = self.event_bus.subscribe();
+
+        loop {
+            match rx.recv().await {
+                Ok(Event::ConfigChanged) => {}
+                Ok(_) => continue,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+
+            let reloaded = Config::load(self.config.source.as_deref())
+                .and_then(|config| self.redactor.reload(&config.redact_patterns));
+            if let Err(e) = reloaded {
+                error!("Failed to reload config: {:#}", e);
+                self.event_bus.publish(Event::SystemNotification {
+                    level: NotificationLevel::Error,
+                    message: format!("Config reload failed, keeping previous settings: {:#}", e),
+                    target: None,
+                });
+            }
+        }
+    }
+
+    /// Turn `ScheduledEvent`s into prompts for the named project's agent.
+    async fn consume_scheduled_events(&self) {
+        let mut rx = self.event_bus.subscribe();
+
+        loop {
+            let (job_id, payload) = match rx.recv().await {
+                Ok(Event::ScheduledEvent { job_id, payload }) => (job_id, payload),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(
+                        "Scheduled event consumer lagged, skipped {} events",
+                        skipped
+                    );
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            info!("Running scheduled job {}", job_id);
+            let result = self.run_scheduled_prompt(&payload).await;
+            if let Err(e) = &result {
+                error!("Scheduled job {} failed: {:#}", job_id, e);
+                self.event_bus.publish(Event::SystemNotification {
+                    level: NotificationLevel::Error,
+                    message: format!("Scheduled job {} failed: {:#}", job_id, e),
+                    target: None,
+                });
+            }
+            self.scheduler.finish(&job_id, &result).await;
+        }
+    }
+
+    /// Queue `job` to be sent to its project's agent at `run_at`. Returns the job id.
+    pub async fn schedule_prompt(
+        &self,
+        job: &ScheduledPrompt,
+        run_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<String> {
+        validate_scheduled_prompt(job)?;
+        let payload = serde_json::to_string(job)?;
+        self.store
+            .enqueue_job(JOB_KIND_PROMPT, &payload, run_at, None)
+            .await
+    }
+
+    /// Queue `job` to be sent to its project's agent every time `schedule`
+    /// comes round (see [`Recurrence`]), until the job is removed with
+    /// [`Manager::remove_job`]. Returns the job id.
+    pub async fn add_scheduled_job(&self, job: &ScheduledPrompt, schedule: &str) -> Result<String> {
+        validate_scheduled_prompt(job)?;
+        let recurrence: Recurrence = schedule.parse()?;
+        let run_at = recurrence
+            .next_after(chrono::Utc::now())
+            .with_context(|| format!("Schedule '{}' never runs", schedule))?;
+        let payload = serde_json::to_string(job)?;
+        let id = self
+            .store
+            .enqueue_job(JOB_KIND_PROMPT, &payload, run_at, Some(schedule.trim()))
+            .await?;
+        info!(
+            "Scheduled recurring job {} ({}), first run at {}",
+            id, schedule, run_at
+        );
+        Ok(id)
+    }
+
+    /// Scheduled jobs that haven't completed, soonest first.
+    pub async fn list_jobs(&self) -> Result<Vec<Job>> {
+        self.store.list_jobs(JOB_LIST_LIMIT).await
+    }
+
+    /// The scheduled job `id`, or None if there is none.
+    pub async fn get_job(&self, id: &str) -> Result<Option<Job>> {
+        self.store.get_job(id).await
+    }
+
+    /// Delete a scheduled job so it never runs (again). A run already under
+    /// way finishes, but its outcome isn't recorded.
+    pub async fn remove_job(&self, id: &str) -> Result<()> {
+        if !self.store.delete_job(id).await? {
+            anyhow::bail!("No job with id {}", id);
+        }
+        info!("Removed scheduled job {}", id);
+        Ok(())
+    }
+
+    /// Run a pending or failed job now instead of at its scheduled time.
+    pub async fn trigger_job_now(&self, id: &str) -> Result<()> {
+        if !self.scheduler.trigger(id).await? {
+            anyhow::bail!("No pending or failed job with id {}", id);
+        }
+        info!("Triggered scheduled job {}", id);
+        Ok(())
+    }
+
+    async fn run_scheduled_prompt(&self, payload: &str) -> Result<()> {
+        let job: ScheduledPrompt =
+            serde_json::from_str(payload).context("Malformed scheduled job payload")?;
+        validate_scheduled_prompt(&job)?;
+
+        let running = self.sessions.lock().unwrap().contains_key(&job.project);
+        if !running {
+            self.launch_project(job.project.clone()).await?;
+        }
+
+        let mut metadata = job.reply_to;
+        metadata.insert(PROJECT_NAME_KEY.to_string(), job.project);
+
+        self.event_bus.publish(Event::ChatMessage(ChatMessage {
+            id: Uuid::new_v4().to_string(),
+            chat_id: Some("scheduler".to_string()),
+            sender: EntityId::new("scheduler", "Scheduler", Role::User),
+            content: job.prompt,
+            timestamp: chrono::Utc::now(),
+            metadata,
+        }));
+
+        Ok(())
+    }
+}
+
+/// [`Manager::read_project_file`] for callers without a `Manager`, e.g. agent
+/// sessions. Blocks on the container runtime.
+pub fn read_project_file_blocking(
+    runtime: &dyn ProjectRuntime,
+    config: &Config,
+    project_name: &str,
+    path: &str,
+    max_bytes: u64,
+) -> Result<Vec<u8>> {
+    let root = config.project_dir(project_name);
+    let target = confine_to_project(&root, project_name, path)?;
+    let cmd = format!(
+        "{READ_PATHS} && f=$(realpath -e -- \"$f\") && case \"$f\" in \"$root\"/*) exec cat -- \"$f\" ;; *) exit {status} ;; esac",
+        status = PATH_ESCAPES_STATUS
+    );
+
+    let mut child = runtime.spawn_exec(project_name, &cmd)?;
+    let mut stdin = child.stdin.take().context("Failed to take stdin")?;
+    // A failed write shows up as the command's exit status below
+    let _ = stdin.write_all(format!("{}\n{}\n", root, target).as_bytes());
+    drop(stdin);
+    let stdout = child.stdout.take().context("Failed to take stdout")?;
+
+    // Read one byte past the limit to tell "exactly max" from "too big"
+    let mut bytes = Vec::new();
+    stdout
+        .take(max_bytes + 1)
+        .read_to_end(&mut bytes)
+        .context("Failed to read file from container")?;
+    if bytes.len() as u64 > max_bytes {
+        let _ = child.kill();
+        let _ = child.wait();
+        anyhow::bail!("{} is larger than {} bytes", path, max_bytes);
+    }
+
+    let status = child.wait()?;
+    if status.code() == Some(PATH_ESCAPES_STATUS) {
+        return Err(PathOutsideProject {
+            project: project_name.to_string(),
+            path: path.to_string(),
+        }
+        .into());
+    }
+    if !status.success() {
+        anyhow::bail!("Reading {} failed: {}", path, status);
+    }
+    Ok(bytes)
+}
+
+/// Resolve `path`, absolute or relative to the project checkout `root`, to an
+/// absolute path strictly inside `root`. Purely lexical: `..` is applied here,
+/// symlinks are checked in the container.
+fn confine_to_project(root: &str, project: &str, path: &str) -> Result<String> {
+    let outside = || PathOutsideProject {
+        project: project.to_string(),
+        path: path.to_string(),
+    };
+    // Paths reach the container as lines on stdin
+    if path.contains(['\n', '\0']) {
+        anyhow::bail!("Unsupported file path: {:?}", path);
+    }
+
+    let root_parts: Vec<&str> = root.split('/').filter(|p| !p.is_empty()).collect();
+    let mut parts = if path.starts_with('/') {
+        Vec::new()
+    } else {
+        root_parts.clone()
+    };
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if parts.pop().is_none() {
+                    return Err(outside().into());
+                }
+            }
+            part => parts.push(part),
+        }
+    }
+
+    if parts.len() <= root_parts.len() || !parts.starts_with(&root_parts) {
+        return Err(outside().into());
+    }
+    Ok(format!("/{}", parts.join("/")))
+}
+
+/// File name to store an upload under: the last component of `name`, with
+/// anything but ASCII letters, digits, `.`, `_` and `-` replaced by `_`, no
+/// leading dots, and at most `MAX_UPLOAD_NAME_CHARS` long (extension kept).
+fn sanitize_upload_name(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let cleaned = cleaned.trim_start_matches('.');
+    if cleaned.is_empty() {
+        return "upload".to_string();
+    }
+    if cleaned.len() <= MAX_UPLOAD_NAME_CHARS {
+        return cleaned.to_string();
+    }
+
+    // All ASCII from here, so byte offsets are char boundaries
+    match cleaned.rfind('.') {
+        Some(dot) if cleaned.len() - dot <= 16 => {
+            let ext = &cleaned[dot..];
+            format!("{}{}", &cleaned[..MAX_UPLOAD_NAME_CHARS - ext.len()], ext)
+        }
+        _ => cleaned[..MAX_UPLOAD_NAME_CHARS].to_string(),
+    }
+}
+
+/// Send each line of `pipe` (lossily decoded, newline included) to `tx` until
+/// EOF or until nobody is listening.
+fn forward_lines(pipe: impl Read, tx: tokio::sync::mpsc::UnboundedSender<String>) {
+    for line in std::io::BufReader::new(pipe).split(b'\n') {
+        let Ok(mut line) = line else { break };
+        line.push(b'\n');
+        if tx
+            .send(String::from_utf8_lossy(&line).into_owned())
+            .is_err()
+        {
+            break;
+        }
     }
 }
 
+/// Whether a filesystem event in the projects directory can add or remove a project.
+fn changes_projects(kind: &notify::EventKind) -> bool {
+    use notify::event::{EventKind, ModifyKind};
+    matches!(
+        kind,
+        EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+    )
+}
+
+fn validate_scheduled_prompt(job: &ScheduledPrompt) -> Result<()> {
+    if job.project.trim().is_empty() || job.prompt.trim().is_empty() {
+        anyhow::bail!("Scheduled job payload needs a non-empty 'project' and 'prompt'");
+    }
+    Ok(())
+}
+
+/// Dispatches due jobs from the store's `jobs` table as `ScheduledEvent`s and
+/// records how they went, retrying failures with backoff. Jobs live in the
+/// database, so they survive restarts.
 pub struct Scheduler {
-    // Placeholder for scheduling logic
+    store: Arc<Store>,
+    event_bus: Arc<EventBus>,
+    // Jobs dispatched and not yet finished, by id
+    in_flight: Mutex<HashMap<String, Job>>,
+    // Set once jobs orphaned by a previous process have been requeued
+    recovered: AtomicBool,
 }
 
 impl Scheduler {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(store: Arc<Store>, event_bus: Arc<EventBus>) -> Self {
+        Self {
+            store,
+            event_bus,
+            in_flight: Mutex::new(HashMap::new()),
+            recovered: AtomicBool::new(false),
+        }
     }
 
     pub async fn start(&self) {
-        // Placeholder for scheduler loop
-        std::future::pending::<()>().await;
+        // Jobs claimed by a process that died never finished; run them again.
+        // Only on the first start, as a restarted loop's jobs may still be running.
+        if !self.recovered.swap(true, Ordering::SeqCst) {
+            match self.store.requeue_running_jobs().await {
+                Ok(0) => {}
+                Ok(n) => info!("Requeued {} interrupted scheduled jobs", n),
+                Err(e) => error!("Failed to requeue interrupted jobs: {:#}", e),
+            }
+        }
+
+        let mut poll = tokio::time::interval(SCHEDULER_POLL_INTERVAL);
+        poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            poll.tick().await;
+            if let Err(e) = self.dispatch_due_jobs().await {
+                error!("Failed to dispatch scheduled jobs: {:#}", e);
+            }
+        }
+    }
+
+    async fn dispatch_due_jobs(&self) -> Result<()> {
+        let jobs = self
+            .store
+            .claim_due_jobs(chrono::Utc::now(), SCHEDULER_BATCH_SIZE)
+            .await?;
+        for job in jobs {
+            self.dispatch(job).await?;
+        }
+        Ok(())
+    }
+
+    /// Dispatch the pending or failed job `id` now, whatever its `run_at`.
+    /// Returns false if there is no such job.
+    pub async fn trigger(&self, id: &str) -> Result<bool> {
+        let Some(job) = self.store.claim_job(id).await? else {
+            return Ok(false);
+        };
+        self.dispatch(job).await?;
+        Ok(true)
+    }
+
+    /// Publish a claimed job as a `ScheduledEvent`.
+    async fn dispatch(&self, job: Job) -> Result<()> {
+        if job.kind != JOB_KIND_PROMPT {
+            let error = format!("Unknown job kind '{}'", job.kind);
+            warn!("Scheduled job {}: {}", job.id, error);
+            return self.store.fail_job(&job.id, &error, None).await;
+        }
+
+        let event = Event::ScheduledEvent {
+            job_id: job.id.clone(),
+            payload: job.payload.clone(),
+        };
+        self.in_flight.lock().unwrap().insert(job.id.clone(), job);
+        self.event_bus.publish(event);
+        Ok(())
+    }
+
+    /// Record the outcome of a dispatched job. Failures are retried after
+    /// `JOB_RETRY_BASE`, doubling, until `JOB_MAX_ATTEMPTS` runs have failed.
+    /// Recurring jobs then wait for their next run instead of ending.
+    pub async fn finish(&self, job_id: &str, result: &Result<()>) {
+        // Events published by someone other than the scheduler have no job row
+        let Some(job) = self.in_flight.lock().unwrap().remove(job_id) else {
+            return;
+        };
+        let next_run =
+            job.schedule
+                .as_deref()
+                .and_then(|schedule| match schedule.parse::<Recurrence>() {
+                    Ok(recurrence) => recurrence.next_after(chrono::Utc::now()),
+                    Err(e) => {
+                        warn!("Scheduled job {} has a bad schedule: {:#}", job_id, e);
+                        None
+                    }
+                });
+
+        let recorded = match (result, next_run) {
+            (Ok(()), Some(next_run)) => self.store.reschedule_job(job_id, next_run).await,
+            (Ok(()), None) => self.store.complete_job(job_id).await,
+            (Err(e), next_run) => {
+                let failures = job.attempts + 1;
+                let retry_at = (failures < JOB_MAX_ATTEMPTS).then(|| {
+                    let delay = JOB_RETRY_BASE * 2u32.pow(failures - 1);
+                    chrono::Utc::now()
+                        + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::zero())
+                });
+                if let Some(retry_at) = retry_at {
+                    info!("Retrying scheduled job {} at {}", job_id, retry_at);
+                }
+                let error = format!("{:#}", e);
+                match (retry_at, next_run) {
+                    // Out of retries, but a recurring job still gets its next run
+                    (None, Some(next_run)) => {
+                        info!("Scheduled job {} runs again at {}", job_id, next_run);
+                        match self.store.fail_job(job_id, &error, None).await {
+                            Ok(()) => self.store.reschedule_job(job_id, next_run).await,
+                            Err(e) => Err(e),
+                        }
+                    }
+                    _ => self.store.fail_job(job_id, &error, retry_at).await,
+                }
+            }
+        };
+        if let Err(e) = recorded {
+            error!("Failed to record outcome of job {}: {:#}", job_id, e);
+        }
+    }
+}
+
+/// A [`Manager`] on a fake runtime and an in-memory store, for tests.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+
+    /// Knows a fixed list of projects and launches succeed. Commands fail,
+    /// unless `local` runs them on the host as if it were every container.
+    pub struct FakeRuntime {
+        pub projects: Vec<String>,
+        pub local: bool,
+    }
+
+    impl ProjectRuntime for FakeRuntime {
+        fn list_projects(&self) -> Result<Vec<String>> {
+            Ok(self.projects.clone())
+        }
+
+        fn launch(&self, _name: &str, _limits: Option<&ResourceLimits>) -> Result<()> {
+            Ok(())
+        }
+
+        fn exec_capture(&self, name: &str, cmd: &str) -> Result<String> {
+            if !self.local {
+                anyhow::bail!("{} is not running", name);
+            }
+            let output = Command::new("sh").arg("-c").arg(cmd).output()?;
+            anyhow::ensure!(output.status.success(), "{} failed", cmd);
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+
+        fn spawn_exec(&self, name: &str, cmd: &str) -> Result<std::process::Child> {
+            if !self.local {
+                anyhow::bail!("{} is not running", name);
+            }
+            Ok(Command::new("sh")
+                .arg("-c")
+                .arg(cmd)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?)
+        }
+
+        fn healthy(&self) -> bool {
+            true
+        }
+    }
+
+    pub async fn memory_store() -> Arc<Store> {
+        let store = Store::new(":memory:").await.unwrap();
+        store.init().await.unwrap();
+        Arc::new(store)
+    }
+
+    pub async fn manager(projects: &[&str], config: Config) -> Arc<Manager> {
+        with_runtime(projects, config, false).await
+    }
+
+    /// A manager whose projects live in `config.projects_dir` on the host.
+    pub async fn local_manager(projects: &[&str], config: Config) -> Arc<Manager> {
+        with_runtime(projects, config, true).await
+    }
+
+    async fn with_runtime(projects: &[&str], config: Config, local: bool) -> Arc<Manager> {
+        let runtime = FakeRuntime {
+            projects: projects.iter().map(|p| p.to_string()).collect(),
+            local,
+        };
+        let manager = Manager::with_runtime(
+            Arc::new(runtime),
+            Arc::new(EventBus::new()),
+            Arc::new(config),
+            memory_store().await,
+        )
+        .unwrap();
+        Arc::new(manager)
+    }
+
+    /// `agent_command` for a scripted ACP agent. It answers `initialize` and
+    /// `session/new`, then runs the shell snippet `on_prompt` for every
+    /// `session/prompt`, with `$id` holding the request id and `$line` the
+    /// request. The snippet talks back with `chunk <text>`, `tool <title>` and
+    /// `reply [stop reason]`.
+    pub fn fake_agent(on_prompt: &str) -> String {
+        format!(
+            r#"
+update() {{ printf '{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"fake","update":%s}}}}\n' "$1"; }}
+chunk() {{ update "{{\"sessionUpdate\":\"agent_message_chunk\",\"content\":{{\"type\":\"text\",\"text\":\"$1\"}}}}"; }}
+tool() {{ update "{{\"sessionUpdate\":\"tool_call\",\"toolCallId\":\"$1\",\"title\":\"$1\"}}"; }}
+reply() {{ printf '{{"jsonrpc":"2.0","id":%s,"result":{{"stopReason":"%s"}}}}\n' "$id" "${{1:-end_turn}}"; }}
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id":\([0-9][0-9]*\).*/\1/p')
+  case "$line" in
+    *'"method":"initialize"'*) printf '{{"jsonrpc":"2.0","id":%s,"result":{{"protocolVersion":1}}}}\n' "$id" ;;
+    *'"method":"session/new"'*) printf '{{"jsonrpc":"2.0","id":%s,"result":{{"sessionId":"fake"}}}}\n' "$id" ;;
+    *'"method":"session/prompt"'*) {on_prompt} ;;
+  esac
+done
+"#
+        )
+    }
+
+    /// A fresh, empty directory to hold projects; symlinks in its path resolved.
+    pub fn temp_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("thalassa-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &str = "/workspace/web";
+
+    fn confine(path: &str) -> Result<String> {
+        confine_to_project(ROOT, "web", path)
+    }
+
+    #[test]
+    fn relative_and_absolute_paths_inside_the_project_are_kept() {
+        assert_eq!(
+            confine("src/main.rs").unwrap(),
+            "/workspace/web/src/main.rs"
+        );
+        assert_eq!(
+            confine("/workspace/web/./out/../report.pdf").unwrap(),
+            "/workspace/web/report.pdf"
+        );
+    }
+
+    #[test]
+    fn paths_leaving_the_project_are_refused() {
+        for path in [
+            "/etc/passwd",
+            "../other/secret",
+            "src/../../other",
+            "/workspace/web",
+            "/workspace/webapp/x",
+            ".",
+        ] {
+            let err = confine(path).unwrap_err();
+            assert!(err.is::<PathOutsideProject>(), "{}: {:#}", path, err);
+        }
+    }
+
+    #[test]
+    fn quotes_are_fine_but_line_breaks_are_not() {
+        assert_eq!(
+            confine("it's $(id).txt").unwrap(),
+            "/workspace/web/it's $(id).txt"
+        );
+        assert!(confine("a\nb").is_err());
+        assert!(confine("a\0b").is_err());
+    }
+
+    #[test]
+    fn upload_names_are_sanitized() {
+        assert_eq!(sanitize_upload_name("report.pdf"), "report.pdf");
+        assert_eq!(sanitize_upload_name("../../etc/passwd"), "passwd");
+        assert_eq!(
+            sanitize_upload_name("C:\\Users\\me\\notes.txt"),
+            "notes.txt"
+        );
+        assert_eq!(sanitize_upload_name("my file (1).txt"), "my_file__1_.txt");
+        assert_eq!(sanitize_upload_name(".bashrc"), "bashrc");
+        assert_eq!(sanitize_upload_name("$(rm -rf ~)'"), "__rm_-rf____");
+        assert_eq!(sanitize_upload_name(".."), "upload");
+        assert_eq!(sanitize_upload_name(""), "upload");
+
+        let long = format!("{}.tar.gz", "a".repeat(300));
+        let sanitized = sanitize_upload_name(&long);
+        assert_eq!(sanitized.len(), MAX_UPLOAD_NAME_CHARS);
+        assert!(sanitized.ends_with("aaa.gz"), "{}", sanitized);
+    }
+
+    async fn upload_manager() -> (Arc<Manager>, std::path::PathBuf) {
+        let dir = testing::temp_dir();
+        std::fs::create_dir(dir.join("web")).unwrap();
+        let config = Config {
+            projects_dir: dir.to_string_lossy().into_owned(),
+            ..Config::default()
+        };
+        (testing::local_manager(&["web"], config).await, dir)
+    }
+
+    #[tokio::test]
+    async fn uploads_never_overwrite_existing_files() {
+        let (manager, dir) = upload_manager().await;
+        let upload = |bytes: &[u8]| {
+            manager.upload_file("web".to_string(), "report.pdf".to_string(), bytes.to_vec())
+        };
+
+        let first = upload(b"first").await.unwrap();
+        let second = upload(b"second").await.unwrap();
+        let third = upload(b"third").await.unwrap();
+
+        let uploads = dir.join("web/uploads");
+        assert_eq!(first, uploads.join("report.pdf").to_string_lossy());
+        assert_eq!(second, uploads.join("report-1.pdf").to_string_lossy());
+        assert_eq!(third, uploads.join("report-2.pdf").to_string_lossy());
+        assert_eq!(std::fs::read(&first).unwrap(), b"first");
+        assert_eq!(std::fs::read(&second).unwrap(), b"second");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn uploads_stay_in_the_uploads_directory() {
+        let (manager, dir) = upload_manager().await;
+
+        let path = manager
+            .upload_file(
+                "web".to_string(),
+                "../../../outside.txt".to_string(),
+                b"x".to_vec(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(path, dir.join("web/uploads/outside.txt").to_string_lossy());
+
+        // A symlinked uploads directory must not lead out of the project
+        std::fs::remove_dir_all(dir.join("web/uploads")).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("web/uploads")).unwrap();
+        let err = manager
+            .upload_file("web".to_string(), "escape.txt".to_string(), b"x".to_vec())
+            .await
+            .unwrap_err();
+        assert!(
+            err.downcast_ref::<PathOutsideProject>().is_some(),
+            "{:#}",
+            err
+        );
+        assert!(!dir.join("escape.txt").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn scheduled_jobs_survive_a_restart() {
+        let dir = testing::temp_dir();
+        let path = dir.join("thalassa.db");
+        let now = chrono::Utc::now();
+
+        let store = Store::new(&path).await.unwrap();
+        store.init().await.unwrap();
+        let id = store
+            .enqueue_job(JOB_KIND_PROMPT, r#"{"prompt":"hi"}"#, now, None)
+            .await
+            .unwrap();
+        // Claimed, then the process dies before the job finishes
+        store.claim_due_jobs(now, 10).await.unwrap();
+        drop(store);
+
+        let store = Arc::new(Store::new(&path).await.unwrap());
+        store.init().await.unwrap();
+        let event_bus = Arc::new(EventBus::new());
+        let mut events = event_bus.subscribe();
+        let scheduler = Arc::new(Scheduler::new(store.clone(), event_bus));
+        let running = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.start().await }
+        });
+
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("the requeued job should run")
+            .unwrap();
+        let Event::ScheduledEvent { job_id, payload } = event else {
+            panic!("unexpected event {:?}", event);
+        };
+        assert_eq!(job_id, id);
+        assert_eq!(payload, r#"{"prompt":"hi"}"#);
+
+        scheduler.finish(&job_id, &Ok(())).await;
+        assert!(store.list_jobs(10).await.unwrap().is_empty());
+        running.abort();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn jobs_can_be_listed_removed_and_run_early() {
+        let event_bus = Arc::new(EventBus::new());
+        let mut events = event_bus.subscribe();
+        let manager = Manager::with_runtime(
+            Arc::new(testing::FakeRuntime {
+                projects: vec!["web".to_string()],
+                local: false,
+            }),
+            event_bus,
+            Arc::new(Config::default()),
+            testing::memory_store().await,
+        )
+        .unwrap();
+        let prompt = |text: &str| ScheduledPrompt {
+            project: "web".to_string(),
+            prompt: text.to_string(),
+            reply_to: HashMap::new(),
+        };
+        let later = chrono::Utc::now() + chrono::Duration::hours(1);
+        let first = manager
+            .schedule_prompt(&prompt("run the tests"), later)
+            .await
+            .unwrap();
+        let second = manager
+            .add_scheduled_job(&prompt("daily report"), "0 9 * * *")
+            .await
+            .unwrap();
+
+        let jobs = manager.list_jobs().await.unwrap();
+        let mut ids: Vec<&str> = jobs.iter().map(|job| job.id.as_str()).collect();
+        ids.sort();
+        let mut expected = [first.as_str(), second.as_str()];
+        expected.sort();
+        assert_eq!(ids, expected);
+
+        manager.trigger_job_now(&first).await.unwrap();
+        match events.try_recv().unwrap() {
+            Event::ScheduledEvent { job_id, payload } => {
+                assert_eq!(job_id, first);
+                assert!(payload.contains("run the tests"), "{}", payload);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        // Already running, so it can't be started a second time
+        let error = manager.trigger_job_now(&first).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("No pending or failed job with id {}", first)
+        );
+
+        manager.remove_job(&second).await.unwrap();
+        let jobs = manager.list_jobs().await.unwrap();
+        assert!(jobs.iter().all(|job| job.id != second));
+        let error = manager.remove_job(&second).await.unwrap_err();
+        assert_eq!(error.to_string(), format!("No job with id {}", second));
+        assert!(manager.trigger_job_now(&second).await.is_err());
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn interval_jobs_fire_every_second() {
+        let store = testing::memory_store().await;
+        let event_bus = Arc::new(EventBus::new());
+        let mut events = event_bus.subscribe();
+        let scheduler = Arc::new(Scheduler::new(store.clone(), event_bus));
+        let id = store
+            .enqueue_job(JOB_KIND_PROMPT, "{}", chrono::Utc::now(), Some("every 1s"))
+            .await
+            .unwrap();
+        let running = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.start().await }
+        });
+
+        let mut fired = 0;
+        let collect = async {
+            while fired < 2 {
+                if let Ok(Event::ScheduledEvent { job_id, .. }) = events.recv().await {
+                    assert_eq!(job_id, id);
+                    fired += 1;
+                    scheduler.finish(&job_id, &Ok(())).await;
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_millis(3500), collect)
+            .await
+            .expect("the job should fire twice within 3s");
+        running.abort();
+    }
+
+    #[tokio::test]
+    async fn finished_jobs_are_done_unless_they_recur() {
+        let store = testing::memory_store().await;
+        let scheduler = Scheduler::new(store.clone(), Arc::new(EventBus::new()));
+        let now = chrono::Utc::now();
+        let once = store
+            .enqueue_job(JOB_KIND_PROMPT, "{}", now, None)
+            .await
+            .unwrap();
+        let hourly = store
+            .enqueue_job(JOB_KIND_PROMPT, "{}", now, Some("every 1h"))
+            .await
+            .unwrap();
+
+        for id in [&once, &hourly] {
+            assert!(scheduler.trigger(id).await.unwrap());
+            scheduler.finish(id, &Ok(())).await;
+        }
+
+        let jobs = store.list_jobs(10).await.unwrap();
+        assert_eq!(jobs.len(), 1);
+        let job = &jobs[0];
+        assert_eq!(job.id, hourly);
+        assert_eq!(job.status, "pending");
+        let next_run = job.run_at - now;
+        assert!(
+            next_run > chrono::Duration::minutes(59) && next_run < chrono::Duration::minutes(61),
+            "{}",
+            next_run
+        );
+    }
+
+    #[tokio::test]
+    async fn recurring_jobs_out_of_retries_wait_for_their_next_run() {
+        let store = testing::memory_store().await;
+        let scheduler = Scheduler::new(store.clone(), Arc::new(EventBus::new()));
+        let id = store
+            .enqueue_job(JOB_KIND_PROMPT, "{}", chrono::Utc::now(), Some("every 1h"))
+            .await
+            .unwrap();
+
+        for _ in 0..JOB_MAX_ATTEMPTS {
+            assert!(scheduler.trigger(&id).await.unwrap());
+            scheduler
+                .finish(&id, &Err(anyhow::anyhow!("web is not running")))
+                .await;
+        }
+
+        let jobs = store.list_jobs(10).await.unwrap();
+        assert_eq!(jobs[0].status, "pending");
+        assert_eq!(jobs[0].attempts, 0);
+        assert_eq!(jobs[0].last_error.as_deref(), Some("web is not running"));
+        assert!(jobs[0].run_at > chrono::Utc::now() + chrono::Duration::minutes(59));
+    }
+
+    #[tokio::test]
+    async fn stopping_a_project_removes_its_session() {
+        let config = Config {
+            agent_command: testing::fake_agent("chunk hi; reply"),
+            ..Config::default()
+        };
+        let manager = testing::local_manager(&["web"], config).await;
+        manager.launch_project("web".to_string()).await.unwrap();
+        assert!(manager.agent_ready("web").await);
+        let session = manager
+            .sessions
+            .lock()
+            .unwrap()
+            .get("web")
+            .cloned()
+            .unwrap();
+        assert!(session.status().await.process_alive);
+        let mut events = manager.event_bus.subscribe();
+
+        manager.stop_project("web").await.unwrap();
+
+        assert!(manager.session_status("web").await.is_none());
+        assert!(manager.all_session_statuses().await.is_empty());
+        let status = session.status().await;
+        assert_eq!(status.state, crate::bus::AgentState::Stopped);
+        assert!(!status.process_alive);
+        let mut notices = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let Event::SystemNotification { message, .. } = event {
+                notices.push(message);
+            }
+        }
+        assert_eq!(notices, ["Stopped the agent for web"]);
+
+        let error = manager.stop_project("web").await.unwrap_err();
+        assert_eq!(error.to_string(), "No agent session is running for web");
+    }
+
+    #[tokio::test]
+    async fn streamed_exec_output_arrives_line_by_line() {
+        let manager = testing::local_manager(&["web"], Config::default()).await;
+        let started = Instant::now();
+        let mut lines = Vec::new();
+
+        let status = manager
+            .exec_command_streaming(
+                "web".to_string(),
+                "echo one; sleep 0.5; echo two >&2; sleep 0.5; printf three; exit 3".to_string(),
+                |line| lines.push((started.elapsed(), line.to_string())),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(status.code(), Some(3));
+        let texts: Vec<&str> = lines.iter().map(|(_, line)| line.as_str()).collect();
+        assert_eq!(texts, ["one\n", "two\n", "three\n"]);
+        // Each line is handed over as printed, not once the command is done
+        assert!(lines[0].0 < Duration::from_millis(400), "{:?}", lines);
+        assert!(lines[1].0 < Duration::from_millis(900), "{:?}", lines);
+        assert!(lines[2].0 >= Duration::from_millis(1000), "{:?}", lines);
+    }
+
+    /// Knows the project "web" and records the limits each launch asks for,
+    /// rejecting them if `reject` is set.
+    struct LimitedRuntime {
+        reject: bool,
+        launches: Mutex<Vec<Option<ResourceLimits>>>,
+    }
+
+    impl ProjectRuntime for LimitedRuntime {
+        fn list_projects(&self) -> Result<Vec<String>> {
+            Ok(vec!["web".to_string()])
+        }
+
+        fn launch(&self, name: &str, limits: Option<&ResourceLimits>) -> Result<()> {
+            self.launches.lock().unwrap().push(limits.cloned());
+            if self.reject {
+                return Err(ResourceLimitsRejected {
+                    project: name.to_string(),
+                    message: "Minimum memory limit allowed is 6MB".to_string(),
+                }
+                .into());
+            }
+            Ok(())
+        }
+
+        fn exec_capture(&self, name: &str, _cmd: &str) -> Result<String> {
+            anyhow::bail!("{} is not running", name)
+        }
+
+        fn spawn_exec(&self, name: &str, _cmd: &str) -> Result<std::process::Child> {
+            anyhow::bail!("{} is not running", name)
+        }
+
+        fn healthy(&self) -> bool {
+            true
+        }
+    }
+
+    async fn limited_manager(reject: bool) -> (Arc<LimitedRuntime>, Manager) {
+        let mut config = Config::default();
+        let limits = ResourceLimits {
+            cpus: Some(1.5),
+            memory: Some("1k".to_string()),
+        };
+        config.project_limits.insert("web".to_string(), limits);
+        let runtime = Arc::new(LimitedRuntime {
+            reject,
+            launches: Mutex::new(Vec::new()),
+        });
+        let manager = Manager::with_runtime(
+            runtime.clone(),
+            Arc::new(EventBus::new()),
+            Arc::new(config),
+            testing::memory_store().await,
+        )
+        .unwrap();
+        (runtime, manager)
+    }
+
+    #[tokio::test]
+    async fn configured_limits_go_to_the_launch_and_show_in_the_project_info() {
+        let (runtime, manager) = limited_manager(false).await;
+        assert_eq!(manager.project_info("web").await.limits, None);
+
+        // The container starts; only the agent, which can't exec, fails
+        let err = manager.launch_project("web".to_string()).await.unwrap_err();
+        assert!(err.downcast_ref::<ResourceLimitsRejected>().is_none());
+
+        let launches = runtime.launches.lock().unwrap().clone();
+        assert_eq!(launches.len(), 1);
+        assert_eq!(launches[0].as_ref(), manager.resource_limits("web"));
+        let info = manager.project_info("web").await;
+        assert!(info.limits.is_some());
+        assert_eq!(info.limits, launches[0]);
+    }
+
+    #[test]
+    fn launch_failures_give_advice_and_the_runtime_message() {
+        let failure = |raw: &str| {
+            let classified = LaunchError::classify(&anyhow::anyhow!(raw.to_string()));
+            launch_failure_message("web", &classified.into())
+        };
+
+        assert_eq!(
+            failure("Error response from daemon: No such image: web:latest"),
+            "Could not start web: its container image is missing (Error response from daemon: \
+             No such image: web:latest). Build the project's image (or check the registry \
+             login) and try again."
+        );
+        assert_eq!(
+            failure("Bind for 0.0.0.0:8080 failed: port is already allocated"),
+            "Could not start web: a port it needs is already in use on the host (Bind for \
+             0.0.0.0:8080 failed: port is already allocated). Stop whatever holds it (often \
+             another copy of this project) and try again."
+        );
+        assert_eq!(
+            failure("write /var/lib/docker/tmp: no space left on device"),
+            "Could not start web: the host is out of disk space or memory (write \
+             /var/lib/docker/tmp: no space left on device). Free some up (e.g. `docker system \
+             prune`) and try again."
+        );
+        assert_eq!(
+            failure("exit status 125"),
+            "Could not start web: exit status 125"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_limits_fail_the_launch_with_a_config_hint() {
+        let (runtime, manager) = limited_manager(true).await;
+
+        let err = manager.launch_project("web".to_string()).await.unwrap_err();
+        assert!(err.downcast_ref::<ResourceLimitsRejected>().is_some());
+        assert_eq!(runtime.launches.lock().unwrap().len(), 1);
+        assert_eq!(
+            launch_failure_message("web", &err),
+            "Could not start web: the container runtime rejected its resource limits \
+             (Minimum memory limit allowed is 6MB). Check project_limits.web in the config."
+        );
+        assert_eq!(manager.project_info("web").await.limits, None);
+    }
+
+    /// Healthy while `up` is set; counts its health checks.
+    #[derive(Default)]
+    struct PingRuntime {
+        up: AtomicBool,
+        pings: std::sync::atomic::AtomicUsize,
+    }
+
+    impl ProjectRuntime for PingRuntime {
+        fn list_projects(&self) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        fn launch(&self, name: &str, _limits: Option<&ResourceLimits>) -> Result<()> {
+            anyhow::bail!("{} is not running", name)
+        }
+
+        fn exec_capture(&self, name: &str, _cmd: &str) -> Result<String> {
+            anyhow::bail!("{} is not running", name)
+        }
+
+        fn spawn_exec(&self, name: &str, _cmd: &str) -> Result<std::process::Child> {
+            anyhow::bail!("{} is not running", name)
+        }
+
+        fn healthy(&self) -> bool {
+            self.pings.fetch_add(1, Ordering::SeqCst);
+            self.up.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn docker_ping_reads_the_status_of_the_daemon_reply() {
+        let dir = testing::temp_dir();
+        let socket = dir.join("docker.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        let daemon = std::thread::spawn(move || {
+            for status in ["200 OK", "500 Internal Server Error"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                std::io::BufReader::new(&stream)
+                    .read_line(&mut request)
+                    .unwrap();
+                assert_eq!(request, "GET /_ping HTTP/1.0\r\n");
+                write!(stream, "HTTP/1.1 {}\r\n\r\n", status).unwrap();
+            }
+        });
+
+        let socket = socket.to_str().unwrap();
+        assert!(docker_ping(socket).unwrap());
+        assert!(!docker_ping(socket).unwrap());
+        daemon.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(docker_ping(socket).is_err());
+    }
+
+    #[tokio::test]
+    async fn runtime_health_is_reused_until_it_expires() {
+        let runtime = Arc::new(PingRuntime::default());
+        runtime.up.store(true, Ordering::SeqCst);
+        let manager = Manager::with_runtime(
+            runtime.clone(),
+            Arc::new(EventBus::new()),
+            Arc::new(Config::default()),
+            testing::memory_store().await,
+        )
+        .unwrap();
+        let ttl = Duration::from_secs(manager.config().runtime_health_ttl_secs);
+        tokio::time::pause();
+
+        assert!(manager.runtime_healthy().await);
+        runtime.up.store(false, Ordering::SeqCst);
+        tokio::time::advance(ttl - Duration::from_millis(1)).await;
+        assert!(manager.runtime_healthy().await);
+        assert_eq!(runtime.pings.load(Ordering::SeqCst), 1);
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        assert!(!manager.runtime_healthy().await);
+        assert_eq!(runtime.pings.load(Ordering::SeqCst), 2);
+    }
+
+    /// Lists the directories in `dir`, like a runtime whose projects are checkouts there.
+    struct DirRuntime {
+        dir: std::path::PathBuf,
+    }
+
+    impl ProjectRuntime for DirRuntime {
+        fn list_projects(&self) -> Result<Vec<String>> {
+            let mut projects = Vec::new();
+            for entry in std::fs::read_dir(&self.dir)? {
+                projects.push(entry?.file_name().to_string_lossy().into_owned());
+            }
+            projects.sort();
+            Ok(projects)
+        }
+
+        fn launch(&self, _name: &str, _limits: Option<&ResourceLimits>) -> Result<()> {
+            Ok(())
+        }
+
+        fn exec_capture(&self, name: &str, _cmd: &str) -> Result<String> {
+            anyhow::bail!("{} is not running", name)
+        }
+
+        fn spawn_exec(&self, name: &str, _cmd: &str) -> Result<std::process::Child> {
+            anyhow::bail!("{} is not running", name)
+        }
+
+        fn healthy(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn the_project_list_is_cached_until_its_ttl_passes() {
+        let dir = testing::temp_dir();
+        std::fs::create_dir(dir.join("web")).unwrap();
+        let config = Config {
+            project_cache_ttl_secs: 5,
+            ..Config::default()
+        };
+        let manager = Manager::with_runtime(
+            Arc::new(DirRuntime { dir: dir.clone() }),
+            Arc::new(EventBus::new()),
+            Arc::new(config),
+            testing::memory_store().await,
+        )
+        .unwrap();
+        tokio::time::pause();
+        assert_eq!(manager.list_projects().await.unwrap(), ["web"]);
+
+        std::fs::create_dir(dir.join("docs")).unwrap();
+        tokio::time::advance(Duration::from_millis(4999)).await;
+        assert_eq!(manager.list_projects().await.unwrap(), ["web"]);
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        assert_eq!(manager.list_projects().await.unwrap(), ["docs", "web"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// A local runtime whose one container is running once launched; counts launches.
+    #[derive(Default)]
+    struct CountingRuntime {
+        running: AtomicBool,
+        launches: std::sync::atomic::AtomicUsize,
+    }
+
+    impl ProjectRuntime for CountingRuntime {
+        fn list_projects(&self) -> Result<Vec<String>> {
+            Ok(vec!["web".to_string()])
+        }
+
+        fn launch(&self, _name: &str, _limits: Option<&ResourceLimits>) -> Result<()> {
+            self.launches.fetch_add(1, Ordering::SeqCst);
+            self.running.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn exec_capture(&self, name: &str, _cmd: &str) -> Result<String> {
+            anyhow::ensure!(
+                self.running.load(Ordering::SeqCst),
+                "{} is not running",
+                name
+            );
+            Ok(String::new())
+        }
+
+        fn spawn_exec(&self, _name: &str, cmd: &str) -> Result<std::process::Child> {
+            Ok(Command::new("sh")
+                .arg("-c")
+                .arg(cmd)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?)
+        }
+
+        fn healthy(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn launching_again_reuses_the_session_and_the_container() {
+        let runtime = Arc::new(CountingRuntime::default());
+        let config = Config {
+            agent_command: testing::fake_agent("reply"),
+            ..Config::default()
+        };
+        let manager = Manager::with_runtime(
+            runtime.clone(),
+            Arc::new(EventBus::new()),
+            Arc::new(config),
+            testing::memory_store().await,
+        )
+        .unwrap();
+        let session_id = |status: Option<SessionStatus>| status.unwrap().session_id;
+
+        manager.launch_project("web".to_string()).await.unwrap();
+        let first = session_id(manager.session_status("web").await);
+        assert_eq!(runtime.launches.load(Ordering::SeqCst), 1);
+
+        // A ready session is kept as it is
+        manager.launch_project("web".to_string()).await.unwrap();
+        assert_eq!(session_id(manager.session_status("web").await), first);
+        assert_eq!(runtime.launches.load(Ordering::SeqCst), 1);
+
+        // Without its session, the running container gets a new one
+        manager.stop_project("web").await.unwrap();
+        manager.launch_project("web".to_string()).await.unwrap();
+        let second = session_id(manager.session_status("web").await);
+        assert_ne!(second, first);
+        assert_eq!(runtime.launches.load(Ordering::SeqCst), 1);
+        manager.stop_all_sessions().await;
+    }
+
+    async fn next_notification(rx: &mut tokio::sync::broadcast::Receiver<Event>) -> String {
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .expect("a project change should be announced")
+                .unwrap();
+            if let Event::SystemNotification { message, .. } = event {
+                return message;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn project_directory_changes_refresh_the_cached_list() {
+        let dir = testing::temp_dir();
+        std::fs::create_dir(dir.join("web")).unwrap();
+        let config = Config {
+            projects_watch_dir: Some(dir.clone()),
+            project_cache_ttl_secs: 3600,
+            ..Config::default()
+        };
+        let manager = Arc::new(
+            Manager::with_runtime(
+                Arc::new(DirRuntime { dir: dir.clone() }),
+                Arc::new(EventBus::new()),
+                Arc::new(config),
+                testing::memory_store().await,
+            )
+            .unwrap(),
+        );
+        let mut events = manager.event_bus.subscribe();
+        let watching = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.watch_projects_dir().await }
+        });
+        // Let the watch start and take its baseline
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(manager.list_projects().await.unwrap(), ["web"]);
+
+        std::fs::create_dir(dir.join("docs")).unwrap();
+        assert_eq!(
+            next_notification(&mut events).await,
+            "New project available: docs"
+        );
+        // Well within the TTL, yet the cache already has the new project
+        assert_eq!(manager.list_projects().await.unwrap(), ["docs", "web"]);
+
+        std::fs::remove_dir(dir.join("web")).unwrap();
+        assert_eq!(next_notification(&mut events).await, "Project removed: web");
+        assert_eq!(manager.list_projects().await.unwrap(), ["docs"]);
+
+        watching.abort();
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
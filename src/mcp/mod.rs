@@ -1 +1,2 @@
+pub mod result;
 pub mod server;
@@ -0,0 +1,70 @@
+//! Shapes of `tools/call` results, so handlers don't hand-build the JSON.
+
+use serde_json::Value;
+
+/// A successful result with a single text block.
+pub fn text_result(text: impl Into<String>) -> Value {
+    serde_json::json!({
+        "content": [{
+            "type": "text",
+            "text": text.into()
+        }]
+    })
+}
+
+/// A tool that ran but failed; the client shows `message` to the model
+/// instead of treating it as a protocol error.
+pub fn error_result(message: impl Into<String>) -> Value {
+    serde_json::json!({
+        "content": [{
+            "type": "text",
+            "text": message.into()
+        }],
+        "isError": true
+    })
+}
+
+/// A result carrying `structured` as `structuredContent` (matching the tool's
+/// `outputSchema`), with `text` as the fallback for clients that predate it.
+pub fn structured_result(text: impl Into<String>, structured: Value) -> Value {
+    let mut result = text_result(text);
+    result["structuredContent"] = structured;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn text_results_hold_one_text_block_and_no_error_flag() {
+        assert_eq!(
+            text_result("done"),
+            json!({ "content": [{ "type": "text", "text": "done" }] })
+        );
+    }
+
+    #[test]
+    fn error_results_are_flagged() {
+        assert_eq!(
+            error_result(String::from("web is not running")),
+            json!({
+                "content": [{ "type": "text", "text": "web is not running" }],
+                "isError": true
+            })
+        );
+    }
+
+    #[test]
+    fn structured_results_keep_the_text_fallback() {
+        let structured = json!({ "name": "web", "running": true });
+        assert_eq!(
+            structured_result("web is running", structured.clone()),
+            json!({
+                "content": [{ "type": "text", "text": "web is running" }],
+                "structuredContent": structured
+            })
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! When recurring scheduled jobs run again: fixed intervals or cron expressions.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How often a recurring job runs, as written in its `schedule`:
+/// `every 30s` (also `m`, `h`, `d`), or a cron expression with an optional
+/// leading seconds field, e.g. `0 9 * * 1-5` for 09:00 UTC on weekdays.
+#[derive(Debug, Clone)]
+pub enum Recurrence {
+    Every(Duration),
+    Cron(Box<cron::Schedule>),
+}
+
+impl Recurrence {
+    /// The first run strictly after `after`, if there is one.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Recurrence::Every(interval) => {
+                Some(after + chrono::Duration::from_std(*interval).ok()?)
+            }
+            Recurrence::Cron(schedule) => schedule.after(&after).next(),
+        }
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(interval) = s.strip_prefix("every ") {
+            return parse_interval(interval.trim()).map(Recurrence::Every);
+        }
+
+        // The cron crate wants seconds; standard five-field crontabs start at :00
+        let expression = if s.split_whitespace().count() == 5 {
+            format!("0 {}", s)
+        } else {
+            s.to_string()
+        };
+        let schedule = cron::Schedule::from_str(&expression)
+            .with_context(|| format!("Invalid schedule '{}'", s))?;
+        Ok(Recurrence::Cron(Box::new(schedule)))
+    }
+}
+
+fn parse_interval(interval: &str) -> Result<Duration> {
+    let split = interval
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(interval.len());
+    let (count, unit) = interval.split_at(split);
+    let count: u64 = count
+        .parse()
+        .with_context(|| format!("Invalid interval '{}'", interval))?;
+    let unit_secs = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        other => anyhow::bail!("Unknown interval unit '{}', use s, m, h or d", other),
+    };
+    if count == 0 {
+        anyhow::bail!("Interval must be longer than zero");
+    }
+    Ok(Duration::from_secs(count.saturating_mul(unit_secs)))
+}
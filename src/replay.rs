@@ -0,0 +1,195 @@
+//! Recording bus events to a JSONL file and feeding them back onto a bus
+//! nothing acts on, to reproduce what a session went through.
+
+use crate::bus::{Event, EventBus};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn};
+
+/// One line of a recording.
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedEvent {
+    at: DateTime<Utc>,
+    event: Event,
+}
+
+/// Append every event from `rx` to `path` until the bus closes. Each line is
+/// flushed as it is written so a crash loses nothing already seen.
+pub async fn record_events(mut rx: broadcast::Receiver<Event>, path: PathBuf) -> Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await
+        .with_context(|| format!("Failed to open event recording {}", path.display()))?;
+    info!("Recording bus events to {}", path.display());
+
+    loop {
+        let event = match rx.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(
+                    "Event recorder lagged, {} events missing from the recording",
+                    skipped
+                );
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+
+        let mut line = serde_json::to_string(&RecordedEvent {
+            at: Utc::now(),
+            event,
+        })?;
+        line.push('\n');
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+    }
+}
+
+/// Replay `path` onto a bus of its own, which only logs what goes by. No
+/// agents, chats or scheduler listen, so prompts, replies and notifications
+/// replay like every other event without reaching anyone.
+pub async fn replay_detached(path: &Path, fast: bool) -> Result<()> {
+    let bus = EventBus::new();
+    let logger = tokio::spawn(log_events(bus.subscribe()));
+    let result = replay_events(&bus, path, fast).await;
+    // Closing the bus lets the logger drain and stop
+    drop(bus);
+    let _ = logger.await;
+    result
+}
+
+async fn log_events(mut rx: broadcast::Receiver<Event>) {
+    loop {
+        match rx.recv().await {
+            Ok(event) => info!("Replayed {:?}", event),
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Replay log lagged, {} events not shown", skipped)
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Publish the events recorded in `path` onto `bus`, keeping their original
+/// spacing unless `fast`.
+pub async fn replay_events(bus: &EventBus, path: &Path, fast: bool) -> Result<()> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("Failed to open event recording {}", path.display()))?;
+    let mut lines = BufReader::new(file).lines();
+    info!("Replaying bus events from {}", path.display());
+
+    let mut previous: Option<DateTime<Utc>> = None;
+    let mut replayed = 0usize;
+    let mut line_no = 0usize;
+    while let Some(line) = lines.next_line().await? {
+        line_no += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let recorded: RecordedEvent = serde_json::from_str(&line).with_context(|| {
+            format!("Malformed event on line {} of {}", line_no, path.display())
+        })?;
+
+        if !fast {
+            if let Some(gap) = previous.and_then(|p| (recorded.at - p).to_std().ok()) {
+                tokio::time::sleep(gap).await;
+            }
+        }
+        previous = Some(recorded.at);
+
+        bus.publish(recorded.event);
+        replayed += 1;
+    }
+
+    info!("Replay finished: {} events published", replayed);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{AgentState, NotificationLevel};
+    use crate::chat::ChatMessage;
+    use crate::entity::{EntityId, Role};
+
+    fn message(role: Role) -> Event {
+        Event::ChatMessage(ChatMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            chat_id: Some("tg:1".to_string()),
+            sender: EntityId::new("someone", "Someone", role),
+            content: "hello".to_string(),
+            timestamp: Utc::now(),
+            metadata: Default::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn recorded_events_replay_in_order() {
+        let path =
+            std::env::temp_dir().join(format!("thalassa-replay-{}.jsonl", uuid::Uuid::new_v4()));
+
+        let bus = EventBus::new();
+        let recorder = tokio::spawn(record_events(bus.subscribe(), path.clone()));
+        let events = [
+            Event::AgentStateChanged {
+                project: "web".to_string(),
+                state: AgentState::Starting,
+            },
+            message(Role::User),
+            message(Role::Agent),
+            Event::SystemNotification {
+                level: NotificationLevel::Info,
+                message: "Started".to_string(),
+                target: None,
+            },
+            Event::Announcement {
+                message: "Maintenance".to_string(),
+            },
+            Event::AgentStateChanged {
+                project: "web".to_string(),
+                state: AgentState::Idle,
+            },
+        ];
+        let recorded: Vec<serde_json::Value> = events
+            .iter()
+            .map(|event| serde_json::to_value(event).unwrap())
+            .collect();
+        for event in events {
+            bus.publish(event);
+        }
+        // Closing the bus ends the recording
+        drop(bus);
+        recorder.await.unwrap().unwrap();
+
+        let replay_bus = EventBus::new();
+        let mut rx = replay_bus.subscribe();
+        replay_events(&replay_bus, &path, true).await.unwrap();
+        replay_detached(&path, true).await.unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let mut replayed = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            replayed.push(event);
+        }
+        assert!(
+            matches!(
+                &replayed[1],
+                Event::ChatMessage(msg) if msg.sender.role == Role::User && msg.content == "hello"
+            ),
+            "the user's message should be replayed: {:?}",
+            replayed
+        );
+        let replayed: Vec<serde_json::Value> = replayed
+            .iter()
+            .map(|event| serde_json::to_value(event).unwrap())
+            .collect();
+        assert_eq!(replayed, recorded);
+    }
+}
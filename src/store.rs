@@ -1,10 +1,187 @@
 use crate::{
+    bus::{AgentState, Event},
     chat::ChatMessage,
-    entity::{EntityId, Role},
+    entity::{AgentEntity, Entity, EntityId, Role, ToolEntity},
 };
 use anyhow::{Context, Result};
-use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, Row, SqlitePool};
-use std::{collections::HashMap, path::Path, str::FromStr};
+use chrono::{DateTime, Utc};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteRow},
+    ConnectOptions, Row, SqlitePool,
+};
+use std::{path::Path, str::FromStr};
+use tokio::sync::{
+    broadcast::{self, error::RecvError, error::TryRecvError},
+    watch,
+};
+use tracing::{error, info, warn};
+
+/// Ordered schema migrations; entry `i` upgrades the schema to version `i + 1`.
+/// Append new migrations to the end and never edit one that has shipped.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema. `IF NOT EXISTS` lets databases created before
+    // versioning existed adopt it without changes.
+    r#"
+    CREATE TABLE IF NOT EXISTS messages (
+        id TEXT PRIMARY KEY,
+        chat_id TEXT,
+        sender TEXT NOT NULL,
+        content TEXT NOT NULL,
+        timestamp DATETIME NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_messages_chat_timestamp ON messages(chat_id, timestamp DESC);
+
+    CREATE TABLE IF NOT EXISTS telegram_users (
+        id INTEGER PRIMARY KEY,
+        username TEXT,
+        first_name TEXT NOT NULL,
+        created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+    );
+    "#,
+    // 2: user feedback on agent replies
+    r#"
+    CREATE TABLE feedback (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        project TEXT,
+        agent_message_id INTEGER,
+        context TEXT,
+        content TEXT NOT NULL,
+        created_at DATETIME NOT NULL
+    );
+    CREATE INDEX idx_feedback_created_at ON feedback(created_at DESC);
+    "#,
+    // 3: known users and agents, so message senders resolve to one display name
+    r#"
+    CREATE TABLE entities (
+        id TEXT PRIMARY KEY,
+        data TEXT NOT NULL,
+        updated_at DATETIME NOT NULL
+    );
+    ALTER TABLE messages ADD COLUMN sender_id TEXT;
+    "#,
+    // 4: durable scheduler queue
+    r#"
+    CREATE TABLE jobs (
+        id TEXT PRIMARY KEY,
+        kind TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        run_at DATETIME NOT NULL,
+        status TEXT NOT NULL DEFAULT 'pending',
+        attempts INTEGER NOT NULL DEFAULT 0,
+        last_error TEXT,
+        updated_at DATETIME NOT NULL
+    );
+    CREATE INDEX idx_jobs_status_run_at ON jobs(status, run_at);
+    "#,
+    // 5: message metadata (routing keys etc.) as a JSON object
+    r#"
+    ALTER TABLE messages ADD COLUMN metadata TEXT;
+    "#,
+    // 6: recurring jobs
+    r#"
+    ALTER TABLE jobs ADD COLUMN schedule TEXT;
+    "#,
+    // 7: the bot each Telegram user last wrote to; NULL is the default bot
+    r#"
+    ALTER TABLE telegram_users ADD COLUMN bot TEXT;
+    "#,
+    // 8: settings chats pick for themselves, keyed like `messages.chat_id`
+    r#"
+    CREATE TABLE chat_preferences (
+        chat_id TEXT PRIMARY KEY,
+        model TEXT,
+        updated_at DATETIME NOT NULL
+    );
+    "#,
+    // 9: reply language of a chat
+    r#"
+    ALTER TABLE chat_preferences ADD COLUMN language TEXT;
+    "#,
+];
+
+/// A user's remark about an agent reply, with enough context to find what it refers to.
+#[derive(Debug, Clone)]
+pub struct Feedback {
+    pub chat_id: i64,
+    pub user_id: i64,
+    /// Project that was active in the chat
+    pub project: Option<String>,
+    /// Telegram message id of the agent reply the feedback is about
+    pub agent_message_id: Option<i64>,
+    /// Text of that reply, as the user saw it
+    pub context: Option<String>,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Settings a chat picked for itself, kept across restarts. None means the default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatPreferences {
+    /// Model chosen with `/model`
+    pub model: Option<String>,
+    /// Reply language chosen with `/lang`
+    pub language: Option<String>,
+}
+
+/// File format of an exported chat history.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Array of `ChatMessage`s
+    #[default]
+    Json,
+    /// `timestamp,sender,content` rows with a header
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            other => anyhow::bail!("Unknown export format: {}", other),
+        }
+    }
+}
+
+/// A queued unit of work, claimed by the scheduler once `run_at` has passed.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: String,
+    /// What the payload means, e.g. [`JOB_KIND_PROMPT`]
+    pub kind: String,
+    pub payload: String,
+    pub run_at: DateTime<Utc>,
+    /// `pending`, `running`, `done` or `failed`
+    pub status: String,
+    /// Failed runs so far
+    pub attempts: u32,
+    /// Why the latest failed run failed
+    pub last_error: Option<String>,
+    /// For recurring jobs, when they run again (see [`crate::recurrence::Recurrence`])
+    pub schedule: Option<String>,
+}
+
+/// Job kind whose payload is a JSON-encoded `ScheduledPrompt`.
+pub const JOB_KIND_PROMPT: &str = "prompt";
+
+// `jobs.status` values. Claimed jobs are `running` until completed or failed.
+const JOB_PENDING: &str = "pending";
+const JOB_RUNNING: &str = "running";
+const JOB_DONE: &str = "done";
+const JOB_FAILED: &str = "failed";
 
 #[derive(Clone, Debug)]
 pub struct Store {
@@ -37,47 +214,94 @@ impl Store {
         Ok(Self { pool })
     }
 
-    /// Initialize the database schema.
+    /// Bring the database schema up to date by applying any pending [`MIGRATIONS`].
     pub async fn init(&self) -> Result<()> {
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS messages (
-                id TEXT PRIMARY KEY,
-                chat_id TEXT,
-                sender TEXT NOT NULL,
-                content TEXT NOT NULL,
-                timestamp DATETIME NOT NULL
-            );
-            CREATE INDEX IF NOT EXISTS idx_messages_chat_timestamp ON messages(chat_id, timestamp DESC);
-            
-            CREATE TABLE IF NOT EXISTS telegram_users (
-                id INTEGER PRIMARY KEY,
-                username TEXT,
-                first_name TEXT NOT NULL,
-                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        sqlx::query("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")
+            .execute(&self.pool)
+            .await
+            .context("Failed to create schema_version table")?;
+
+        let current = self.schema_version().await?;
+        if current > MIGRATIONS.len() {
+            anyhow::bail!(
+                "Database schema version {} is newer than this build supports ({})",
+                current,
+                MIGRATIONS.len()
             );
-            "#
-        )
-        .execute(&self.pool)
-        .await
-        .context("Failed to initialize database schema")?;
+        }
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+            let version = index + 1;
+
+            // Each migration and its version bump commit together, so a failed
+            // upgrade is retried from the same point on the next start
+            let mut tx = self.pool.begin().await?;
+            sqlx::query(migration)
+                .execute(&mut *tx)
+                .await
+                .with_context(|| format!("Failed to apply database migration {}", version))?;
+            sqlx::query("DELETE FROM schema_version")
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("INSERT INTO schema_version (version) VALUES (?)")
+                .bind(version as i64)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit()
+                .await
+                .with_context(|| format!("Failed to commit database migration {}", version))?;
+
+            info!("Applied database migration {}", version);
+        }
 
         Ok(())
     }
 
+    /// Schema version recorded in the database; 0 for a fresh database.
+    async fn schema_version(&self) -> Result<usize> {
+        let row = sqlx::query("SELECT COALESCE(MAX(version), 0) AS version FROM schema_version")
+            .fetch_one(&self.pool)
+            .await
+            .context("Failed to read schema version")?;
+
+        Ok(row.try_get::<i64, _>("version")? as usize)
+    }
+
+    /// Whether the database answers a trivial query.
+    pub async fn healthy(&self) -> bool {
+        sqlx::query("SELECT 1").execute(&self.pool).await.is_ok()
+    }
+
+    /// Close the connection pool; every later query fails.
+    #[cfg(test)]
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
     /// Save a chat message to the store.
+    /// Saving the same message id again updates the stored row instead of failing,
+    /// so retries and redelivered events are harmless.
     pub async fn save_message(&self, msg: &ChatMessage) -> Result<()> {
         sqlx::query(
             r#"
-            INSERT INTO messages (id, chat_id, sender, content, timestamp)
-            VALUES (?, ?, ?, ?, ?)
+            INSERT INTO messages (id, chat_id, sender, sender_id, content, timestamp, metadata)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                chat_id = excluded.chat_id,
+                sender = excluded.sender,
+                sender_id = excluded.sender_id,
+                content = excluded.content,
+                timestamp = excluded.timestamp,
+                metadata = excluded.metadata
             "#,
         )
         .bind(&msg.id)
         .bind(&msg.chat_id)
         .bind(msg.sender.to_string())
+        .bind(&msg.sender.id)
         .bind(&msg.content)
         .bind(msg.timestamp)
+        .bind(serde_json::to_string(&msg.metadata)?)
         .execute(&self.pool)
         .await
         .context("Failed to save message")?;
@@ -85,15 +309,85 @@ impl Store {
         Ok(())
     }
 
+    /// Save every chat message arriving on `rx` until `shutdown` turns true, then
+    /// store whatever was already received and return. Subscribe `rx` before the
+    /// bus gets busy so nothing published early is missed.
+    pub async fn persist_messages(
+        &self,
+        mut rx: broadcast::Receiver<Event>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        loop {
+            tokio::select! {
+                // Check shutdown first so a busy bus can't starve it
+                biased;
+                changed = shutdown.changed() => {
+                    if changed.is_err() || *shutdown.borrow() {
+                        break;
+                    }
+                }
+                event = rx.recv() => match event {
+                    Ok(Event::ChatMessage(msg)) => self.persist(&msg).await,
+                    // Agents and their tools become known senders as soon as the session starts
+                    Ok(Event::AgentStateChanged { project, state: AgentState::Starting }) => {
+                        let tools = Entity::Tool(ToolEntity { project_name: project.clone() });
+                        let agent = Entity::Agent(AgentEntity { project_name: project });
+                        for entity in [agent, tools] {
+                            if let Err(e) = self.save_entity(&entity).await {
+                                error!("Failed to register sender entity: {:#}", e);
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Message persister lagged, {} events were not stored", skipped);
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+            }
+        }
+
+        // Drain what was published before the shutdown signal
+        let mut drained = 0;
+        loop {
+            match rx.try_recv() {
+                Ok(Event::ChatMessage(msg)) => {
+                    self.persist(&msg).await;
+                    drained += 1;
+                }
+                Ok(_) => {}
+                Err(TryRecvError::Lagged(skipped)) => {
+                    warn!(
+                        "Message persister lagged, {} events were not stored",
+                        skipped
+                    );
+                }
+                Err(_) => break,
+            }
+        }
+        info!(
+            "Message persister stopped after flushing {} messages",
+            drained
+        );
+    }
+
+    async fn persist(&self, msg: &ChatMessage) {
+        if let Err(e) = self.save_message(msg).await {
+            error!("Failed to persist message {}: {:#}", msg.id, e);
+        }
+    }
+
     /// Retrieve chat history for a specific chat session.
     /// Returns messages ordered by timestamp ascending (oldest to newest).
     pub async fn get_chat_history(&self, chat_id: &str, limit: i64) -> Result<Vec<ChatMessage>> {
         let rows = sqlx::query(
             r#"
-            SELECT id, chat_id, sender, content, timestamp
-            FROM messages
-            WHERE chat_id = ?
-            ORDER BY timestamp DESC
+            SELECT m.id, m.chat_id, m.sender, m.content, m.timestamp, m.metadata,
+                e.data AS sender_entity
+            FROM messages m
+            LEFT JOIN entities e ON e.id = m.sender_id
+            WHERE m.chat_id = ?
+            ORDER BY m.timestamp DESC
             LIMIT ?
             "#,
         )
@@ -103,37 +397,10 @@ impl Store {
         .await
         .context("Failed to fetch chat history")?;
 
-        let mut messages = Vec::with_capacity(rows.len());
-
-        for row in rows {
-            let sender_str: String = row.try_get("sender")?;
-            // We need to deserialize the sender string back into an EntityId
-            // But wait, EntityId::new takes (id, name, role).
-            // We only stored a string representation.
-            // Ideally we should store JSON or normalized fields.
-            // For now, let's assume the string format is "Name (ID)" and parse it, or just use a default role.
-            // Actually, `sender.to_string()` output format is `Name (ID)`.
-            // Let's just create a generic "Historical" entity if we can't parse perfectly,
-            // or better yet, fix `save_message` to store structured data if we want structured read.
-            // For this iteration, let's treat it as a generic User/Agent based on content or just Unknown role.
-
-            let sender = if sender_str.starts_with("Agent") {
-                EntityId::new(sender_str.clone(), sender_str, Role::Agent)
-            } else if sender_str == "System (system)" {
-                EntityId::system()
-            } else {
-                EntityId::new(sender_str.clone(), sender_str, Role::User)
-            };
-
-            messages.push(ChatMessage {
-                id: row.try_get("id")?,
-                chat_id: row.try_get("chat_id")?,
-                sender,
-                content: row.try_get("content")?,
-                timestamp: row.try_get("timestamp")?,
-                metadata: HashMap::new(), // Metadata not stored in DB yet
-            });
-        }
+        let mut messages = rows
+            .iter()
+            .map(message_from_row)
+            .collect::<Result<Vec<_>>>()?;
 
         // Return in chronological order (oldest -> newest)
         messages.reverse();
@@ -141,24 +408,1158 @@ impl Store {
         Ok(messages)
     }
 
-    /// Save or update a Telegram user.
-    pub async fn save_telegram_user(&self, user: &crate::entity::TelegramUser) -> Result<()> {
+    /// Every stored message of `chat_id`, oldest first, rendered as `format`.
+    pub async fn export_chat_history(&self, chat_id: &str, format: ExportFormat) -> Result<String> {
+        let messages = self.get_chat_history(chat_id, i64::MAX).await?;
+
+        match format {
+            ExportFormat::Json => {
+                serde_json::to_string_pretty(&messages).context("Failed to serialize chat history")
+            }
+            ExportFormat::Csv => {
+                let mut csv = String::from("timestamp,sender,content\r\n");
+                for msg in &messages {
+                    csv.push_str(&format!(
+                        "{},{},{}\r\n",
+                        csv_field(&msg.timestamp.to_rfc3339()),
+                        csv_field(&msg.sender.to_string()),
+                        csv_field(&msg.content)
+                    ));
+                }
+                Ok(csv)
+            }
+        }
+    }
+
+    /// The message with `id`, if it was stored.
+    pub async fn get_message_by_id(&self, id: &str) -> Result<Option<ChatMessage>> {
+        let row = sqlx::query(
+            r#"
+            SELECT m.id, m.chat_id, m.sender, m.content, m.timestamp, m.metadata,
+                e.data AS sender_entity
+            FROM messages m
+            LEFT JOIN entities e ON e.id = m.sender_id
+            WHERE m.id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch message")?;
+
+        row.as_ref().map(message_from_row).transpose()
+    }
+
+    /// Most recent message of each chat, newest chat first, for a conversation list.
+    pub async fn get_last_message_per_chat(&self, limit: i64) -> Result<Vec<ChatMessage>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT m.id, m.chat_id, m.sender, m.content, m.timestamp, m.metadata,
+                e.data AS sender_entity
+            FROM (
+                SELECT *, ROW_NUMBER() OVER (
+                    PARTITION BY chat_id ORDER BY timestamp DESC, id DESC
+                ) AS rank
+                FROM messages
+                WHERE chat_id IS NOT NULL
+            ) m
+            LEFT JOIN entities e ON e.id = m.sender_id
+            WHERE m.rank = 1
+            ORDER BY m.timestamp DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch latest message per chat")?;
+
+        rows.iter().map(message_from_row).collect()
+    }
+
+    /// Save or update a user or agent, keyed by its `EntityId.id`. Stored
+    /// messages from it then load with its current name.
+    pub async fn save_entity(&self, entity: &Entity) -> Result<()> {
+        let data = serde_json::to_string(entity).context("Failed to serialize entity")?;
         sqlx::query(
             r#"
-            INSERT INTO telegram_users (id, username, first_name)
+            INSERT INTO entities (id, data, updated_at)
             VALUES (?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                data = excluded.data,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(entity.id().id)
+        .bind(data)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await
+        .context("Failed to save entity")?;
+
+        Ok(())
+    }
+
+    /// Save or update a Telegram user who just wrote to `bot` (None for the
+    /// default bot).
+    pub async fn save_telegram_user(
+        &self,
+        user: &crate::entity::TelegramUser,
+        bot: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO telegram_users (id, username, first_name, bot)
+            VALUES (?, ?, ?, ?)
             ON CONFLICT(id) DO UPDATE SET
                 username = excluded.username,
-                first_name = excluded.first_name
+                first_name = excluded.first_name,
+                bot = excluded.bot
             "#,
         )
         .bind(user.id)
         .bind(&user.username)
         .bind(&user.first_name)
+        .bind(bot)
         .execute(&self.pool)
         .await
         .context("Failed to save telegram user")?;
 
         Ok(())
     }
+
+    /// Ids of the registered Telegram users who last wrote to `bot` (None for
+    /// the default bot). For private chats these double as chat ids.
+    pub async fn list_telegram_user_ids(&self, bot: Option<&str>) -> Result<Vec<i64>> {
+        let rows = sqlx::query("SELECT id FROM telegram_users WHERE bot IS ?")
+            .bind(bot)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list telegram users")?;
+
+        rows.iter()
+            .map(|row| row.try_get::<i64, _>("id").map_err(Into::into))
+            .collect()
+    }
+
+    /// Whether the Telegram user `id` has registered and last wrote to `bot`
+    /// (None for the default bot).
+    pub async fn has_telegram_user(&self, id: i64, bot: Option<&str>) -> Result<bool> {
+        let row = sqlx::query("SELECT 1 FROM telegram_users WHERE id = ? AND bot IS ?")
+            .bind(id)
+            .bind(bot)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to look up telegram user")?;
+
+        Ok(row.is_some())
+    }
+
+    /// Id of the registered Telegram user with `username`, if any.
+    pub async fn find_telegram_user_id(&self, username: &str) -> Result<Option<i64>> {
+        let row = sqlx::query("SELECT id FROM telegram_users WHERE username = ?")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to look up telegram user")?;
+
+        row.map(|row| row.try_get::<i64, _>("id"))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Record user feedback. Returns the id of the stored entry.
+    pub async fn save_feedback(&self, feedback: &Feedback) -> Result<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO feedback (chat_id, user_id, project, agent_message_id, context, content, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(feedback.chat_id)
+        .bind(feedback.user_id)
+        .bind(&feedback.project)
+        .bind(feedback.agent_message_id)
+        .bind(&feedback.context)
+        .bind(&feedback.content)
+        .bind(feedback.created_at)
+        .execute(&self.pool)
+        .await
+        .context("Failed to save feedback")?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Most recent feedback entries, newest first.
+    pub async fn list_feedback(&self, limit: i64) -> Result<Vec<Feedback>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT chat_id, user_id, project, agent_message_id, context, content, created_at
+            FROM feedback
+            ORDER BY created_at DESC, id DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to list feedback")?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(Feedback {
+                    chat_id: row.try_get("chat_id")?,
+                    user_id: row.try_get("user_id")?,
+                    project: row.try_get("project")?,
+                    agent_message_id: row.try_get("agent_message_id")?,
+                    context: row.try_get("context")?,
+                    content: row.try_get("content")?,
+                    created_at: row.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+
+    /// The preferences stored for `chat_id`; all defaults if it has none.
+    pub async fn chat_preferences(&self, chat_id: &str) -> Result<ChatPreferences> {
+        let row = sqlx::query("SELECT model, language FROM chat_preferences WHERE chat_id = ?")
+            .bind(chat_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to load chat preferences")?;
+
+        match row {
+            Some(row) => Ok(ChatPreferences {
+                model: row.try_get("model")?,
+                language: row.try_get("language")?,
+            }),
+            None => Ok(ChatPreferences::default()),
+        }
+    }
+
+    /// Remember the model `chat_id` uses; None goes back to the default.
+    pub async fn set_chat_model(&self, chat_id: &str, model: Option<&str>) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO chat_preferences (chat_id, model, updated_at)
+            VALUES (?, ?, ?)
+            ON CONFLICT(chat_id) DO UPDATE SET
+                model = excluded.model,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(chat_id)
+        .bind(model)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await
+        .context("Failed to save chat model")?;
+        Ok(())
+    }
+
+    /// Remember the language `chat_id` wants replies in; None goes back to the default.
+    pub async fn set_chat_language(&self, chat_id: &str, language: Option<&str>) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO chat_preferences (chat_id, language, updated_at)
+            VALUES (?, ?, ?)
+            ON CONFLICT(chat_id) DO UPDATE SET
+                language = excluded.language,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(chat_id)
+        .bind(language)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await
+        .context("Failed to save chat language")?;
+        Ok(())
+    }
+
+    /// Queue a job to run at `run_at`, and with a `schedule` again after each
+    /// run. Returns its id.
+    pub async fn enqueue_job(
+        &self,
+        kind: &str,
+        payload: &str,
+        run_at: DateTime<Utc>,
+        schedule: Option<&str>,
+    ) -> Result<String> {
+        let id = uuid::Uuid::new_v4().to_string();
+        sqlx::query(
+            r#"
+            INSERT INTO jobs (id, kind, payload, run_at, schedule, status, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&id)
+        .bind(kind)
+        .bind(payload)
+        .bind(run_at)
+        .bind(schedule)
+        .bind(JOB_PENDING)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await
+        .context("Failed to enqueue job")?;
+
+        Ok(id)
+    }
+
+    /// Mark up to `limit` pending jobs due by `now` as running and return them,
+    /// oldest first. A claimed job isn't handed out again until it fails with a retry.
+    pub async fn claim_due_jobs(&self, now: DateTime<Utc>, limit: i64) -> Result<Vec<Job>> {
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query(
+            r#"
+            SELECT id, kind, payload, run_at, status, attempts, last_error, schedule
+            FROM jobs
+            WHERE status = ? AND run_at <= ?
+            ORDER BY run_at ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(JOB_PENDING)
+        .bind(now)
+        .bind(limit)
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to fetch due jobs")?;
+
+        let mut jobs = rows.iter().map(job_from_row).collect::<Result<Vec<_>>>()?;
+
+        for job in &mut jobs {
+            sqlx::query("UPDATE jobs SET status = ?, updated_at = ? WHERE id = ?")
+                .bind(JOB_RUNNING)
+                .bind(Utc::now())
+                .bind(&job.id)
+                .execute(&mut *tx)
+                .await
+                .context("Failed to claim job")?;
+            job.status = JOB_RUNNING.to_string();
+        }
+        tx.commit().await.context("Failed to claim jobs")?;
+
+        Ok(jobs)
+    }
+
+    /// Claim the job `id` as [`Store::claim_due_jobs`] would, whenever it is
+    /// due. Only pending and failed jobs can be claimed; `None` otherwise.
+    pub async fn claim_job(&self, id: &str) -> Result<Option<Job>> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query(
+            r#"
+            SELECT id, kind, payload, run_at, status, attempts, last_error, schedule
+            FROM jobs
+            WHERE id = ? AND status IN (?, ?)
+            "#,
+        )
+        .bind(id)
+        .bind(JOB_PENDING)
+        .bind(JOB_FAILED)
+        .fetch_optional(&mut *tx)
+        .await
+        .context("Failed to fetch job")?;
+        let Some(mut job) = row.as_ref().map(job_from_row).transpose()? else {
+            return Ok(None);
+        };
+
+        sqlx::query("UPDATE jobs SET status = ?, updated_at = ? WHERE id = ?")
+            .bind(JOB_RUNNING)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to claim job")?;
+        tx.commit().await.context("Failed to claim job")?;
+
+        job.status = JOB_RUNNING.to_string();
+        Ok(Some(job))
+    }
+
+    /// Jobs that haven't completed (pending, running or failed), soonest first.
+    pub async fn list_jobs(&self, limit: i64) -> Result<Vec<Job>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, kind, payload, run_at, status, attempts, last_error, schedule
+            FROM jobs
+            WHERE status != ?
+            ORDER BY run_at ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(JOB_DONE)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to list jobs")?;
+
+        rows.iter().map(job_from_row).collect()
+    }
+
+    /// The job `id`, whatever its status, or None if there is none.
+    pub async fn get_job(&self, id: &str) -> Result<Option<Job>> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, kind, payload, run_at, status, attempts, last_error, schedule
+            FROM jobs
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to load job")?;
+
+        row.as_ref().map(job_from_row).transpose()
+    }
+
+    /// Delete the job `id`. Returns whether there was one.
+    pub async fn delete_job(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM jobs WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete job")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Mark a claimed job as finished successfully.
+    pub async fn complete_job(&self, id: &str) -> Result<()> {
+        sqlx::query("UPDATE jobs SET status = ?, last_error = NULL, updated_at = ? WHERE id = ?")
+            .bind(JOB_DONE)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to complete job")?;
+
+        Ok(())
+    }
+
+    /// Record a failed run of a claimed job. With `retry_at` it goes back to
+    /// pending for that time, otherwise it stays failed.
+    pub async fn fail_job(
+        &self,
+        id: &str,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let status = if retry_at.is_some() {
+            JOB_PENDING
+        } else {
+            JOB_FAILED
+        };
+        sqlx::query(
+            r#"
+            UPDATE jobs
+            SET status = ?, attempts = attempts + 1, last_error = ?,
+                run_at = COALESCE(?, run_at), updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(status)
+        .bind(error)
+        .bind(retry_at)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .context("Failed to record job failure")?;
+
+        Ok(())
+    }
+
+    /// Put a claimed recurring job back in the queue for its next run at
+    /// `run_at`, with a clean slate of attempts. `last_error` is kept.
+    pub async fn reschedule_job(&self, id: &str, run_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            "UPDATE jobs SET status = ?, attempts = 0, run_at = ?, updated_at = ? WHERE id = ?",
+        )
+        .bind(JOB_PENDING)
+        .bind(run_at)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .context("Failed to reschedule job")?;
+
+        Ok(())
+    }
+
+    /// Put jobs left running by a previous process back in the queue.
+    /// Returns how many were requeued.
+    pub async fn requeue_running_jobs(&self) -> Result<u64> {
+        let result = sqlx::query("UPDATE jobs SET status = ?, updated_at = ? WHERE status = ?")
+            .bind(JOB_PENDING)
+            .bind(Utc::now())
+            .bind(JOB_RUNNING)
+            .execute(&self.pool)
+            .await
+            .context("Failed to requeue interrupted jobs")?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Quote a CSV field (RFC 4180) if it holds a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Rebuild a `Job` from a `jobs` row.
+fn job_from_row(row: &SqliteRow) -> Result<Job> {
+    Ok(Job {
+        id: row.try_get("id")?,
+        kind: row.try_get("kind")?,
+        payload: row.try_get("payload")?,
+        run_at: row.try_get("run_at")?,
+        status: row.try_get("status")?,
+        attempts: row.try_get::<i64, _>("attempts")? as u32,
+        last_error: row.try_get("last_error")?,
+        schedule: row.try_get("schedule")?,
+    })
+}
+
+/// Rebuild a `ChatMessage` from a `messages` row.
+fn message_from_row(row: &SqliteRow) -> Result<ChatMessage> {
+    let sender_str: String = row.try_get("sender")?;
+    // We need to deserialize the sender string back into an EntityId
+    // But wait, EntityId::new takes (id, name, role).
+    // We only stored a string representation.
+    // Ideally we should store JSON or normalized fields.
+    // For now, let's assume the string format is "Name (ID)" and parse it, or just use a default role.
+    // Actually, `sender.to_string()` output format is `Name (ID)`.
+    // Let's just create a generic "Historical" entity if we can't parse perfectly,
+    // or better yet, fix `save_message` to store structured data if we want structured read.
+    // For this iteration, let's treat it as a generic User/Agent based on content or just Unknown role.
+
+    // Known senders resolve through `entities`; older rows fall back to guessing
+    let known_sender = row
+        .try_get::<Option<String>, _>("sender_entity")?
+        .and_then(|data| serde_json::from_str::<Entity>(&data).ok());
+
+    let sender = if let Some(entity) = known_sender {
+        entity.id()
+    } else if sender_str.starts_with("Agent") {
+        EntityId::new(sender_str.clone(), sender_str, Role::Agent)
+    } else if sender_str.starts_with("Tools (") {
+        EntityId::new(sender_str.clone(), sender_str, Role::Tool)
+    } else if sender_str == "System (system)" {
+        EntityId::system()
+    } else {
+        EntityId::new(sender_str.clone(), sender_str, Role::User)
+    };
+
+    Ok(ChatMessage {
+        id: row.try_get("id")?,
+        chat_id: row.try_get("chat_id")?,
+        sender,
+        content: row.try_get("content")?,
+        timestamp: row.try_get("timestamp")?,
+        // Rows saved before metadata was stored have none
+        metadata: row
+            .try_get::<Option<String>, _>("metadata")?
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn store() -> Store {
+        let store = Store::new(":memory:").await.unwrap();
+        store.init().await.unwrap();
+        store
+    }
+
+    fn ids(jobs: &[Job]) -> Vec<&str> {
+        jobs.iter().map(|job| job.id.as_str()).collect()
+    }
+
+    /// Names of the tables in the store's database.
+    async fn tables(store: &Store) -> Vec<String> {
+        sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+            .fetch_all(&store.pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_fresh_database_gets_every_migration() {
+        let store = store().await;
+        assert_eq!(store.schema_version().await.unwrap(), MIGRATIONS.len());
+        for table in ["entities", "feedback", "jobs", "messages", "telegram_users"] {
+            assert!(
+                tables(&store).await.contains(&table.to_string()),
+                "{}",
+                table
+            );
+        }
+        // The last migration's column is there
+        store
+            .save_telegram_user(
+                &crate::entity::TelegramUser {
+                    id: 1,
+                    username: None,
+                    first_name: "Ann".to_string(),
+                },
+                Some("staging"),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn migrating_again_changes_nothing() {
+        let dir = crate::manager::testing::temp_dir();
+        let path = dir.join("thalassa.db");
+        let store = Store::new(&path).await.unwrap();
+        store.init().await.unwrap();
+        let msg = message("kept", Entity::System.id(), Utc::now());
+        store.save_message(&msg).await.unwrap();
+        store.init().await.unwrap();
+        drop(store);
+
+        // Restarting runs the migrations against the same file again
+        let store = Store::new(&path).await.unwrap();
+        store.init().await.unwrap();
+        assert_eq!(store.schema_version().await.unwrap(), MIGRATIONS.len());
+        let versions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM schema_version")
+            .fetch_one(&store.pool)
+            .await
+            .unwrap();
+        assert_eq!(versions, 1);
+        assert!(store.get_message_by_id("kept").await.unwrap().is_some());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_database_from_before_versioning_is_upgraded_in_place() {
+        let store = Store::new(":memory:").await.unwrap();
+        // The schema as it was before migrations were tracked
+        sqlx::query(
+            r#"
+            CREATE TABLE messages (
+                id TEXT PRIMARY KEY,
+                chat_id TEXT,
+                sender TEXT NOT NULL,
+                content TEXT NOT NULL,
+                timestamp DATETIME NOT NULL
+            );
+            CREATE TABLE telegram_users (
+                id INTEGER PRIMARY KEY,
+                username TEXT,
+                first_name TEXT NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            );
+            "#,
+        )
+        .execute(&store.pool)
+        .await
+        .unwrap();
+        let old = message("old", Entity::System.id(), Utc::now());
+        sqlx::query(
+            "INSERT INTO messages (id, chat_id, sender, content, timestamp) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&old.id)
+        .bind(&old.chat_id)
+        .bind(old.sender.to_string())
+        .bind(&old.content)
+        .bind(old.timestamp)
+        .execute(&store.pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO telegram_users (id, username, first_name) VALUES (7, 'ann', 'Ann')",
+        )
+        .execute(&store.pool)
+        .await
+        .unwrap();
+
+        store.init().await.unwrap();
+
+        assert_eq!(store.schema_version().await.unwrap(), MIGRATIONS.len());
+        let loaded = store.get_message_by_id("old").await.unwrap().unwrap();
+        assert_eq!(loaded.content, old.content);
+        assert_eq!(loaded.sender, old.sender);
+        assert!(loaded.metadata.is_empty());
+        // Users from before multiple bots count as the default bot's
+        assert!(store.has_telegram_user(7, None).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn due_jobs_are_claimed_once_and_completed() {
+        let store = store().await;
+        let now = Utc::now();
+        let due = store
+            .enqueue_job(
+                JOB_KIND_PROMPT,
+                "{}",
+                now - chrono::Duration::seconds(1),
+                None,
+            )
+            .await
+            .unwrap();
+        let later = store
+            .enqueue_job(
+                JOB_KIND_PROMPT,
+                "{}",
+                now + chrono::Duration::hours(1),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let claimed = store.claim_due_jobs(now, 10).await.unwrap();
+        assert_eq!(ids(&claimed), [due.as_str()]);
+        assert_eq!(claimed[0].status, JOB_RUNNING);
+        assert!(store.claim_due_jobs(now, 10).await.unwrap().is_empty());
+
+        store.complete_job(&due).await.unwrap();
+        let pending = store.list_jobs(10).await.unwrap();
+        assert_eq!(ids(&pending), [later.as_str()]);
+        assert_eq!(pending[0].status, JOB_PENDING);
+    }
+
+    #[tokio::test]
+    async fn failed_jobs_wait_for_their_retry() {
+        let store = store().await;
+        let now = Utc::now();
+        let id = store
+            .enqueue_job(JOB_KIND_PROMPT, "{}", now, None)
+            .await
+            .unwrap();
+        store.claim_due_jobs(now, 10).await.unwrap();
+
+        let retry_at = now + chrono::Duration::seconds(30);
+        store
+            .fail_job(&id, "agent busy", Some(retry_at))
+            .await
+            .unwrap();
+        let job = &store.list_jobs(10).await.unwrap()[0];
+        assert_eq!(job.status, JOB_PENDING);
+        assert_eq!(job.attempts, 1);
+        assert_eq!(job.last_error.as_deref(), Some("agent busy"));
+        assert!(store.claim_due_jobs(now, 10).await.unwrap().is_empty());
+        assert_eq!(
+            ids(&store.claim_due_jobs(retry_at, 10).await.unwrap()),
+            [id.as_str()]
+        );
+
+        store.fail_job(&id, "agent gone", None).await.unwrap();
+        let job = &store.list_jobs(10).await.unwrap()[0];
+        assert_eq!(job.status, JOB_FAILED);
+        assert_eq!(job.attempts, 2);
+        assert!(store.claim_due_jobs(retry_at, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn stored_tool_messages_keep_their_role() {
+        let store = store().await;
+        let bus = crate::bus::EventBus::new();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let rx = bus.subscribe();
+
+        bus.publish(Event::AgentStateChanged {
+            project: "web".to_string(),
+            state: AgentState::Starting,
+        });
+        bus.publish(Event::ChatMessage(ChatMessage {
+            id: "tool-1".to_string(),
+            chat_id: Some("tg:42".to_string()),
+            sender: Entity::Tool(ToolEntity {
+                project_name: "web".to_string(),
+            })
+            .id(),
+            content: "🔧 cargo test".to_string(),
+            timestamp: Utc::now(),
+            metadata: Default::default(),
+        }));
+        let persisting = store.persist_messages(rx, shutdown_rx);
+        let stop = async {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            shutdown_tx.send(true).unwrap();
+        };
+        tokio::join!(persisting, stop);
+
+        let history = store.get_chat_history("tg:42", 10).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(
+            history[0].sender,
+            EntityId::new("tool-web", "Tools (web)", Role::Tool)
+        );
+        assert_eq!(history[0].content, "🔧 cargo test");
+    }
+
+    fn message(id: &str, sender: EntityId, timestamp: DateTime<Utc>) -> ChatMessage {
+        ChatMessage {
+            id: id.to_string(),
+            chat_id: Some("tg:42".to_string()),
+            sender,
+            content: format!("message {}", id),
+            timestamp,
+            metadata: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn every_kind_of_entity_round_trips_as_a_message_sender() {
+        let store = store().await;
+        let alice = |username: &str| {
+            Entity::User(crate::entity::TelegramUser {
+                id: 7,
+                username: Some(username.to_string()),
+                first_name: "Alice".to_string(),
+            })
+        };
+        let entities = [
+            Entity::System,
+            alice("alice"),
+            Entity::Agent(AgentEntity {
+                project_name: "web".to_string(),
+            }),
+            Entity::Tool(ToolEntity {
+                project_name: "web".to_string(),
+            }),
+        ];
+        let start = Utc::now();
+        for (i, entity) in entities.iter().enumerate() {
+            store.save_entity(entity).await.unwrap();
+            let at = start + chrono::Duration::seconds(i as i64);
+            let msg = message(&i.to_string(), entity.id(), at);
+            store.save_message(&msg).await.unwrap();
+        }
+
+        let senders = |history: Vec<ChatMessage>| -> Vec<EntityId> {
+            history.into_iter().map(|msg| msg.sender).collect()
+        };
+        let history = store.get_chat_history("tg:42", 10).await.unwrap();
+        let expected: Vec<EntityId> = entities.iter().map(Entity::id).collect();
+        assert_eq!(senders(history), expected);
+
+        // A renamed user's earlier messages show the new name
+        store.save_entity(&alice("alice_b")).await.unwrap();
+        let history = store.get_chat_history("tg:42", 10).await.unwrap();
+        assert_eq!(history[1].sender, EntityId::new("7", "alice_b", Role::User));
+    }
+
+    #[tokio::test]
+    async fn the_latest_message_of_each_chat_comes_newest_chat_first() {
+        let store = store().await;
+        let start = Utc::now();
+        let user = Entity::System.id();
+        // (chat, message id, seconds after start), written out of order
+        let rows = [
+            ("tg:1", "a1", 0),
+            ("tg:2", "b1", 1),
+            ("tg:1", "a2", 4),
+            ("tg:3", "c1", 2),
+            ("tg:2", "b2", 5),
+            ("tg:3", "c2", 3),
+            ("tg:1", "a0", -1),
+        ];
+        for (chat, id, secs) in rows {
+            let mut msg = message(id, user.clone(), start + chrono::Duration::seconds(secs));
+            msg.chat_id = Some(chat.to_string());
+            store.save_message(&msg).await.unwrap();
+        }
+        // Messages outside any chat are not a conversation
+        let mut loose = message("loose", user.clone(), start + chrono::Duration::seconds(9));
+        loose.chat_id = None;
+        store.save_message(&loose).await.unwrap();
+
+        let latest = store.get_last_message_per_chat(10).await.unwrap();
+        let found: Vec<(&str, &str)> = latest
+            .iter()
+            .map(|msg| (msg.chat_id.as_deref().unwrap(), msg.id.as_str()))
+            .collect();
+        assert_eq!(found, [("tg:2", "b2"), ("tg:1", "a2"), ("tg:3", "c2")]);
+
+        let latest = store.get_last_message_per_chat(2).await.unwrap();
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[1].id, "a2");
+    }
+
+    #[tokio::test]
+    async fn exports_escape_quotes_line_breaks_and_commas() {
+        let store = store().await;
+        let jane = Entity::User(crate::entity::TelegramUser {
+            id: 9,
+            username: None,
+            first_name: "Doe, Jane".to_string(),
+        });
+        store.save_entity(&jane).await.unwrap();
+        let start = Utc::now();
+        let contents = [
+            "plain",
+            "say \"hi\", then leave",
+            "line one\nline two",
+            "windows\r\nline",
+        ];
+        for (i, content) in contents.iter().enumerate() {
+            let at = start + chrono::Duration::seconds(i as i64);
+            let mut msg = message(&i.to_string(), jane.id(), at);
+            msg.content = content.to_string();
+            store.save_message(&msg).await.unwrap();
+        }
+
+        let csv = store
+            .export_chat_history("tg:42", ExportFormat::Csv)
+            .await
+            .unwrap();
+        let time = |i: i64| (start + chrono::Duration::seconds(i)).to_rfc3339();
+        let expected = format!(
+            "timestamp,sender,content\r\n\
+             {},\"Doe, Jane (9)\",plain\r\n\
+             {},\"Doe, Jane (9)\",\"say \"\"hi\"\", then leave\"\r\n\
+             {},\"Doe, Jane (9)\",\"line one\nline two\"\r\n\
+             {},\"Doe, Jane (9)\",\"windows\r\nline\"\r\n",
+            time(0),
+            time(1),
+            time(2),
+            time(3)
+        );
+        assert_eq!(csv, expected);
+
+        let json = store
+            .export_chat_history("tg:42", ExportFormat::Json)
+            .await
+            .unwrap();
+        let exported: Vec<ChatMessage> = serde_json::from_str(&json).unwrap();
+        let exported: Vec<&str> = exported.iter().map(|msg| msg.content.as_str()).collect();
+        assert_eq!(exported, contents);
+    }
+
+    #[tokio::test]
+    async fn messages_published_before_shutdown_are_all_stored() {
+        let store = store().await;
+        let (tx, rx) = broadcast::channel(16);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let persister = tokio::spawn({
+            let store = store.clone();
+            async move { store.persist_messages(rx, shutdown_rx).await }
+        });
+
+        // Everything is published and shutdown requested before the persister runs
+        let sender = Entity::System.id();
+        let start = Utc::now();
+        for i in 0..3 {
+            let at = start + chrono::Duration::seconds(i);
+            tx.send(Event::ChatMessage(message(
+                &i.to_string(),
+                sender.clone(),
+                at,
+            )))
+            .unwrap();
+        }
+        shutdown_tx.send(true).unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), persister)
+            .await
+            .expect("the persister should stop")
+            .unwrap();
+
+        let history = store.get_chat_history("tg:42", 10).await.unwrap();
+        let ids: Vec<&str> = history.iter().map(|msg| msg.id.as_str()).collect();
+        assert_eq!(ids, ["0", "1", "2"]);
+
+        // Nothing is stored once it has stopped
+        let _ = tx.send(Event::ChatMessage(message("late", sender, start)));
+        assert!(store.get_message_by_id("late").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn saving_a_message_again_updates_its_one_row() {
+        let store = store().await;
+        let mut msg = message("same", Entity::System.id(), Utc::now());
+        store.save_message(&msg).await.unwrap();
+        store.save_message(&msg).await.unwrap();
+        msg.content = "edited".to_string();
+        store.save_message(&msg).await.unwrap();
+
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages")
+            .fetch_one(&store.pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 1);
+        let history = store.get_chat_history("tg:42", 10).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].content, "edited");
+    }
+
+    #[tokio::test]
+    async fn chat_models_are_kept_per_chat() {
+        let store = store().await;
+        assert_eq!(
+            store.chat_preferences("tg:42").await.unwrap(),
+            ChatPreferences::default()
+        );
+
+        store
+            .set_chat_model("tg:42", Some("gpt-4.1"))
+            .await
+            .unwrap();
+        store.set_chat_model("tg:43", Some("sonnet")).await.unwrap();
+        store.set_chat_model("tg:42", Some("haiku")).await.unwrap();
+        assert_eq!(
+            store
+                .chat_preferences("tg:42")
+                .await
+                .unwrap()
+                .model
+                .as_deref(),
+            Some("haiku")
+        );
+        assert_eq!(
+            store
+                .chat_preferences("tg:43")
+                .await
+                .unwrap()
+                .model
+                .as_deref(),
+            Some("sonnet")
+        );
+
+        store.set_chat_model("tg:42", None).await.unwrap();
+        assert_eq!(store.chat_preferences("tg:42").await.unwrap().model, None);
+    }
+
+    #[tokio::test]
+    async fn chat_languages_are_kept_next_to_the_model() {
+        let store = store().await;
+        store.set_chat_model("tg:42", Some("haiku")).await.unwrap();
+        store
+            .set_chat_language("tg:42", Some("French"))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.chat_preferences("tg:42").await.unwrap(),
+            ChatPreferences {
+                model: Some("haiku".to_string()),
+                language: Some("French".to_string()),
+            }
+        );
+
+        store.set_chat_language("tg:42", None).await.unwrap();
+        let preferences = store.chat_preferences("tg:42").await.unwrap();
+        assert_eq!(preferences.language, None);
+        assert_eq!(preferences.model.as_deref(), Some("haiku"));
+    }
+
+    #[tokio::test]
+    async fn feedback_round_trips_newest_first() {
+        let store = store().await;
+        let start = Utc::now();
+        let full = Feedback {
+            chat_id: 42,
+            user_id: 7,
+            project: Some("web".to_string()),
+            agent_message_id: Some(1001),
+            context: Some("The build passes.".to_string()),
+            content: "It does not".to_string(),
+            created_at: start,
+        };
+        let bare = Feedback {
+            chat_id: 43,
+            user_id: 8,
+            project: None,
+            agent_message_id: None,
+            context: None,
+            content: "Too slow".to_string(),
+            created_at: start + chrono::Duration::seconds(1),
+        };
+        let first = store.save_feedback(&full).await.unwrap();
+        let second = store.save_feedback(&bare).await.unwrap();
+        assert!(second > first);
+
+        let listed = store.list_feedback(10).await.unwrap();
+        assert_eq!(listed.len(), 2);
+        let (newest, oldest) = (&listed[0], &listed[1]);
+        assert_eq!((newest.chat_id, newest.user_id), (43, 8));
+        assert_eq!(newest.content, "Too slow");
+        assert!(newest.project.is_none() && newest.agent_message_id.is_none());
+        assert!(newest.context.is_none());
+        assert_eq!((oldest.chat_id, oldest.user_id), (42, 7));
+        assert_eq!(oldest.project.as_deref(), Some("web"));
+        assert_eq!(oldest.agent_message_id, Some(1001));
+        assert_eq!(oldest.context.as_deref(), Some("The build passes."));
+        assert_eq!(oldest.content, "It does not");
+        assert_eq!(oldest.created_at, start);
+
+        assert_eq!(store.list_feedback(1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn messages_are_found_by_id() {
+        let store = store().await;
+        let mut msg = message("m1", Entity::System.id(), Utc::now());
+        msg.metadata
+            .insert("telegram_message_id".to_string(), "1001".to_string());
+        store.save_message(&msg).await.unwrap();
+
+        let found = store.get_message_by_id("m1").await.unwrap().unwrap();
+        assert_eq!(found.chat_id, msg.chat_id);
+        assert_eq!(found.sender, msg.sender);
+        assert_eq!(found.content, msg.content);
+        assert_eq!(found.timestamp, msg.timestamp);
+        assert_eq!(found.metadata, msg.metadata);
+
+        assert!(store.get_message_by_id("m2").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn message_metadata_survives_a_restart() {
+        let dir = crate::manager::testing::temp_dir();
+        let path = dir.join("thalassa.db");
+        let store = Store::new(&path).await.unwrap();
+        store.init().await.unwrap();
+        let metadata = std::collections::HashMap::from([
+            ("telegram_chat_id".to_string(), "42".to_string()),
+            ("project_name".to_string(), "web".to_string()),
+        ]);
+        store
+            .save_message(&ChatMessage {
+                id: "msg-1".to_string(),
+                chat_id: Some("tg:42".to_string()),
+                sender: EntityId::new("user-1", "alice", Role::User),
+                content: "hello".to_string(),
+                timestamp: Utc::now(),
+                metadata: metadata.clone(),
+            })
+            .await
+            .unwrap();
+        drop(store);
+
+        let store = Store::new(&path).await.unwrap();
+        store.init().await.unwrap();
+        let history = store.get_chat_history("tg:42", 10).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].metadata, metadata);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn messages_stored_without_metadata_load_with_none() {
+        let store = store().await;
+        sqlx::query(
+            "INSERT INTO messages (id, chat_id, sender, sender_id, content, timestamp, metadata) \
+             VALUES ('old-1', 'tg:42', 'alice', 'user-1', 'hi', ?, NULL)",
+        )
+        .bind(Utc::now())
+        .execute(&store.pool)
+        .await
+        .unwrap();
+
+        let history = store.get_chat_history("tg:42", 10).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert!(history[0].metadata.is_empty());
+    }
 }
@@ -0,0 +1,62 @@
+//! HTTPS for the HTTP server, optionally requiring client certificates.
+
+use crate::config::TlsConfig;
+use anyhow::{Context, Result};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Build the rustls server config described by `tls`, or `None` when TLS is
+/// not configured. With `client_ca` set, handshakes without a certificate
+/// signed by that CA fail.
+pub fn server_config(tls: &TlsConfig) -> Result<Option<Arc<ServerConfig>>> {
+    let (Some(cert), Some(key)) = (&tls.cert, &tls.key) else {
+        return Ok(None);
+    };
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let client_verifier = match &tls.client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(ca)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("Invalid CA certificate in {}", ca.display()))?;
+            }
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .context("Failed to set up client certificate verification")?
+        }
+        None => WebPkiClientVerifier::no_client_auth(),
+    };
+
+    let config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .context("Failed to select TLS versions")?
+        .with_client_cert_verifier(client_verifier)
+        .with_single_cert(read_certs(cert)?, read_key(key)?)
+        .context("TLS certificate and key don't form a usable pair")?;
+    Ok(Some(Arc::new(config)))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let pem = std::fs::read(path)
+        .with_context(|| format!("Failed to read certificates from {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Malformed certificate in {}", path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {}", path.display());
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let pem = std::fs::read(path)
+        .with_context(|| format!("Failed to read private key from {}", path.display()))?;
+    rustls_pemfile::private_key(&mut pem.as_slice())
+        .with_context(|| format!("Malformed private key in {}", path.display()))?
+        .with_context(|| format!("No private key found in {}", path.display()))
+}